@@ -1,41 +1,49 @@
-use mlua::{Error as LuaError, IntoLua, Lua, Value};
 use serde::{Deserialize, Serialize};
-#[derive(Debug, Clone, Serialize, Deserialize)]
+
+/// An item applied to the race.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(usize)]
+#[allow(missing_docs)]
 pub enum Action {
-    Acceleration(u32) = 0,
-    Banana(usize) = 1,
-    Shell(usize) = 2,
+    /// Speed added to the car.
+    Acceleration { car: usize, amount: u32 } = 0,
+    /// A banana dropped where the car stands.
+    Banana { car: usize } = 1,
+    /// Shells fired by the car.
+    Shell { car: usize, amount: u32 } = 2,
 }
 
-impl<'lua> IntoLua<'lua> for Action {
-    fn into_lua(self, lua: &'lua Lua) -> color_eyre::Result<Value<'lua>, LuaError> {
-        let table = lua.create_table()?;
+impl Action {
+    /// Index of accelerations in the game's per-item counters, such as the actions sold.
+    pub const ACCELERATION: usize = 0;
+    /// Index of bananas in the game's per-item counters.
+    pub const BANANA: usize = 1;
+    /// Index of shells in the game's per-item counters.
+    pub const SHELL: usize = 2;
+
+    /// The item's index in the game's per-item counters.
+    pub fn into_usize(self) -> usize {
         match self {
-            Action::Acceleration(amount) => {
-                table.set("type", "acceleration")?;
-                table.set("amount", amount)?;
-            }
-            Action::Banana(index) => {
-                table.set("type", "banana")?;
-                table.set("index", index)?;
-            }
-            Action::Shell(index) => {
-                table.set("type", "shell")?;
-                table.set("index", index)?;
-            }
+            Action::Acceleration { .. } => Self::ACCELERATION,
+            Action::Banana { .. } => Self::BANANA,
+            Action::Shell { .. } => Self::SHELL,
         }
+    }
 
-        Ok(Value::Table(table))
+    /// The car that bought the item.
+    pub fn car(&self) -> usize {
+        match *self {
+            Action::Acceleration { car, .. }
+            | Action::Banana { car }
+            | Action::Shell { car, .. } => car,
+        }
     }
-}
 
-impl Action {
-    pub fn into_usize(self) -> usize {
-        match self {
-            Action::Acceleration(_) => 0,
-            Action::Banana(_) => 1,
-            Action::Shell(_) => 2,
+    /// How many items: one for a banana.
+    pub fn amount(&self) -> u32 {
+        match *self {
+            Action::Acceleration { amount, .. } | Action::Shell { amount, .. } => amount,
+            Action::Banana { .. } => 1,
         }
     }
 }
@@ -1,37 +1,45 @@
-use mlua::prelude::LuaError;
-use mlua::{IntoLua, Lua, Value};
 use serde::{Deserialize, Serialize};
 
-const STARTING_BALANCE: u128 = 17500;
+use crate::runtime::Runner;
 
+/// A car in a race, and the program driving it.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Car {
+    /// What the car has left to spend.
     pub balance: u128,
+    /// Distance the car moves every turn.
     pub speed: u32,
+    /// Distance the car covered.
     pub y: u32,
-    #[serde(skip_serializing)]
-    pub lua_script: String,
+    /// The car's program. Cars read back from a log have none.
+    #[serde(skip)]
+    pub runner: Option<Box<dyn Runner>>,
+    /// The name the car registered under.
     pub name: String,
+    /// A disqualified car stays where it is and plays no more turns.
+    pub disqualified: bool,
 }
 
 impl Car {
-    pub const fn new(script: String, name: String) -> Self {
+    /// Creates a car with no balance; registering it in a race gives it the race's starting
+    /// balance.
+    pub fn new(runner: Box<dyn Runner>, name: String) -> Self {
         Self {
-            balance: STARTING_BALANCE,
+            balance: 0,
             speed: 0,
             y: 0,
-            lua_script: script,
+            runner: Some(runner),
             name,
+            disqualified: false,
         }
     }
-}
 
-impl<'lua> IntoLua<'lua> for Car {
-    fn into_lua(self, lua: &'lua Lua) -> color_eyre::Result<Value<'lua>, LuaError> {
-        let table = lua.create_table()?;
-        table.set("balance", self.balance)?;
-        table.set("speed", self.speed)?;
-        table.set("y", self.y)?;
-        Ok(Value::Table(table))
+    /// A copy of the car without its program, as recorded in logs.
+    pub fn snapshot(&self) -> Self {
+        Self {
+            runner: None,
+            name: self.name.clone(),
+            ..*self
+        }
     }
 }
@@ -0,0 +1,242 @@
+use std::path::Path;
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::action::Action;
+use crate::pricing::{ExponentialVrgda, FixedPrice, LinearVrgda, LogisticVrgda, PricingModel};
+
+/// The rules of a race. Every field has a default, so a config file only needs the fields it
+/// changes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RaceConfig {
+    /// Fewest cars the race can start with.
+    pub min_players: usize,
+    /// Most cars the race can start with.
+    pub max_players: usize,
+    /// Distance a car has to reach to win.
+    pub finish_distance: u32,
+    /// Most turns the race lasts, after which it ends without a winner.
+    pub max_turns: usize,
+    /// Balance every car starts with.
+    pub starting_balance: u64,
+    /// Pricing of one unit of speed.
+    #[serde(deserialize_with = "pricing")]
+    pub acceleration: ItemPricing,
+    /// Pricing of a banana.
+    #[serde(deserialize_with = "pricing")]
+    pub banana: ItemPricing,
+    /// Pricing of a shell.
+    #[serde(deserialize_with = "pricing")]
+    pub shell: ItemPricing,
+}
+
+/// An item's pricing model and its parameters, see the README.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+#[allow(missing_docs)]
+pub enum ItemPricing {
+    Exponential(ExponentialVrgda),
+    Linear(LinearVrgda),
+    Logistic(LogisticVrgda),
+    Fixed(FixedPrice),
+}
+
+impl ItemPricing {
+    /// The model, to price items with.
+    pub fn model(&self) -> &dyn PricingModel {
+        match self {
+            Self::Exponential(model) => model,
+            Self::Linear(model) => model,
+            Self::Logistic(model) => model,
+            Self::Fixed(model) => model,
+        }
+    }
+}
+
+/// Reads an item's pricing, which uses the exponential VRGDA when it doesn't name a `model`.
+fn pricing<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ItemPricing, D::Error> {
+    let mut pricing = serde_json::Value::deserialize(deserializer)?;
+    if let Some(fields) = pricing.as_object_mut() {
+        fields.entry("model").or_insert("exponential".into());
+    }
+    serde_json::from_value(pricing).map_err(D::Error::custom)
+}
+
+impl Default for RaceConfig {
+    fn default() -> Self {
+        Self {
+            min_players: 2,
+            max_players: 8,
+            finish_distance: 1000,
+            max_turns: 1000,
+            starting_balance: 17500,
+            acceleration: ItemPricing::Exponential(ExponentialVrgda {
+                target_price: 10,
+                per_turn_decrease: 0.33,
+                sell_per_turn: 2.0,
+            }),
+            banana: ItemPricing::Exponential(ExponentialVrgda {
+                target_price: 200,
+                per_turn_decrease: 0.33,
+                sell_per_turn: 0.2,
+            }),
+            shell: ItemPricing::Exponential(ExponentialVrgda {
+                target_price: 200,
+                per_turn_decrease: 0.33,
+                sell_per_turn: 0.2,
+            }),
+        }
+    }
+}
+
+impl RaceConfig {
+    /// The pricing of an item, by its index in [`Action`]'s counters.
+    pub fn pricing(&self, item: usize) -> &ItemPricing {
+        match item {
+            Action::ACCELERATION => &self.acceleration,
+            Action::BANANA => &self.banana,
+            _ => &self.shell,
+        }
+    }
+
+    /// Reads a config from a `.json` file, or from a TOML file for any other extension.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+        let config: Self = if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            serde_json::from_str(&content).map_err(|e| e.to_string())?
+        } else {
+            toml::from_str(&content).map_err(|e| e.to_string())?
+        };
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Checks that the player counts, the turn cap and the pricing parameters make sense.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_players == 0 || self.min_players > self.max_players {
+            return Err("min_players must be between 1 and max_players".to_string());
+        }
+        if self.max_turns == 0 {
+            return Err("max_turns must be at least 1".to_string());
+        }
+
+        for (item, pricing) in [
+            ("acceleration", &self.acceleration),
+            ("banana", &self.banana),
+            ("shell", &self.shell),
+        ] {
+            pricing
+                .model()
+                .validate()
+                .map_err(|e| format!("{}.{}", item, e))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_toml() {
+        let config: RaceConfig = toml::from_str(
+            r#"
+            finish_distance = 500
+
+            [banana]
+            target_price = 50
+            per_turn_decrease = 0.5
+            sell_per_turn = 1.0
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.finish_distance, 500);
+        assert_eq!(
+            config.banana,
+            ItemPricing::Exponential(ExponentialVrgda {
+                target_price: 50,
+                per_turn_decrease: 0.5,
+                sell_per_turn: 1.0
+            })
+        );
+        assert_eq!(config.shell, RaceConfig::default().shell);
+        assert!(config.validate().is_ok());
+
+        assert!(toml::from_str::<RaceConfig>("finish = 500").is_err());
+    }
+
+    #[test]
+    fn pricing_models() {
+        let config: RaceConfig = toml::from_str(
+            r#"
+            [acceleration]
+            model = "linear"
+            target_price = 10
+            per_turn_decrease = 0.1
+            sell_per_turn = 2
+
+            [banana]
+            model = "fixed"
+            price = 150
+
+            [shell]
+            model = "logistic"
+            target_price = 200
+            per_turn_decrease = 0.33
+            max_sellable = 40
+            time_scale = 0.05
+            "#,
+        )
+        .unwrap();
+
+        assert!(matches!(config.acceleration, ItemPricing::Linear(_)));
+        assert_eq!(
+            config.pricing(Action::BANANA),
+            &ItemPricing::Fixed(FixedPrice { price: 150 })
+        );
+        assert!(matches!(config.shell, ItemPricing::Logistic(_)));
+        assert!(config.validate().is_ok());
+
+        // round trips through the log
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(serde_json::from_str::<RaceConfig>(&json).unwrap(), config);
+
+        let fixed_with_a_schedule = "[banana]\nmodel = \"fixed\"\nprice = 1\nsell_per_turn = 2.0";
+        assert!(toml::from_str::<RaceConfig>(fixed_with_a_schedule).is_err());
+    }
+
+    #[test]
+    fn validate() {
+        let config = RaceConfig {
+            shell: ItemPricing::Exponential(ExponentialVrgda {
+                target_price: 200,
+                per_turn_decrease: 1.0,
+                sell_per_turn: 0.2,
+            }),
+            ..RaceConfig::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err("shell.per_turn_decrease must be between 0 and 1".to_string())
+        );
+
+        let config = RaceConfig {
+            min_players: 4,
+            max_players: 3,
+            ..RaceConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+}
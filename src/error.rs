@@ -0,0 +1,56 @@
+use std::fmt::{self, Display};
+
+use crate::runtime::{Budget, Penalty};
+
+/// Everything that can go wrong setting up or running a race.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum GameError {
+    /// Cars can only register, and the race only start, before it has started.
+    AlreadyStarted,
+    /// The race has to be started before it is run.
+    NotStarted,
+    /// The race already has `max_players` cars.
+    RaceFull { max_players: usize },
+    /// The race was started with fewer than `min` or more than `max` cars.
+    PlayerCount {
+        min: usize,
+        max: usize,
+        registered: usize,
+    },
+    /// A car went over one of its budgets and gets `penalty`.
+    BudgetExceeded { budget: Budget, penalty: Penalty },
+    /// A car's program failed to load or to play its turn.
+    Script(String),
+    /// Replaying a race's log did not lead to the state it recorded for `turn`.
+    Divergence { turn: usize, message: String },
+    /// The race's log could not be streamed.
+    Log(String),
+}
+
+impl Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlreadyStarted => f.write_str("The race already started"),
+            Self::NotStarted => f.write_str("The race has not started"),
+            Self::RaceFull { max_players } => {
+                write!(f, "The race is full ({} players)", max_players)
+            }
+            Self::PlayerCount {
+                min,
+                max,
+                registered,
+            } => write!(
+                f,
+                "The race needs between {} and {} players, {} registered",
+                min, max, registered
+            ),
+            Self::BudgetExceeded { budget, .. } => write!(f, "went over its budget of {}", budget),
+            Self::Script(message) => f.write_str(message),
+            Self::Divergence { turn, message } => write!(f, "Turn {}: {}", turn, message),
+            Self::Log(message) => write!(f, "Failed to write the log: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
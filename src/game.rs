@@ -1,32 +1,33 @@
+use std::cmp::Reverse;
+use std::fmt::Debug;
 use std::io::Write;
-use std::sync::{Arc, Mutex};
-
-use mlua::{Error as LuaError, Function, IntoLua, Lua, UserData, Value};
-use rand::Rng;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use serde::{Deserialize, Serialize};
 
 use crate::action::Action;
 use crate::car::Car;
-use crate::log::Log;
+use crate::config::RaceConfig;
+use crate::error::GameError;
+use crate::log::{Event, Header, Log, LogSink, Outcome, Purchase, RaceLog, Record, LOG_VERSION};
+use crate::runtime::{Budget, Penalty};
 use std::fs::File;
 
-const PLAYERS_REQUIRED: usize = 3;
-const FINISH_DISTANCE: u32 = 1000;
-
-const ACCELERATE_TARGET_PRICE: u128 = 10;
-const ACCELERATE_PER_TURN_DECREASE: f64 = 0.33;
-const ACCELERATE_SELL_PER_TURN: u128 = 2;
-
-const BANANA_TARGET_PRICE: u128 = 200;
-const BANANA_PER_TURN_DECREASE: f64 = 0.33;
-const BANANA_SELL_PER_TURN: f64 = 0.2;
-
-const SHELL_TARGET_PRICE: u128 = 200;
-const SHELL_PER_TURN_DECREASE: f64 = 0.33;
-const SHELL_SELL_PER_TURN: f64 = 0.2;
-
+/// A race between cars.
+///
+/// Cars register while the race is [`State::Waiting`], [`Game::start`] closes registration and
+/// [`Game::race`] plays turns until a car crosses the finish line. Every turn each car's
+/// [`Runner`](crate::Runner) buys items with the `buy_*` methods on its own copy of the race,
+/// then the items are applied and the cars move.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Game {
+    config: RaceConfig,
     state: State,
     turns: usize,
     cars: Vec<Car>,
@@ -35,19 +36,62 @@ pub struct Game {
     winner: Option<usize>,
     actions_sold: Vec<u128>,
     seed: u64,
+    /// The engine's only source of randomness, seeded from `seed`.
+    rng: ChaCha8Rng,
+    #[serde(skip)]
+    index: usize,
+    #[serde(skip)]
+    turn_seed: u64,
+    /// What the car playing bought so far this turn.
+    #[serde(skip)]
+    purchases: Vec<Purchase>,
+    /// How long each car has to decide a turn.
+    #[serde(skip)]
+    time_budget: Option<Duration>,
+    /// When the car playing must have decided its turn by.
+    #[serde(skip)]
+    deadline: Option<Instant>,
+    #[serde(skip)]
+    log_sink: Option<LogSink>,
 }
 
+/// Where a race is at.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum State {
+    /// Cars can register.
     Waiting,
+    /// The race is on.
     Active,
+    /// A car won, or every car was disqualified.
     Done,
 }
 
+/// What buying accelerations, a banana and shells together costs, from [`Game::quote`]. A cost
+/// the car can't afford is `u128::MAX`, and so is the total then.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quote {
+    /// What the accelerations cost.
+    pub acceleration: u128,
+    /// What the bananas cost.
+    pub banana: u128,
+    /// What the shells cost.
+    pub shell: u128,
+    /// What they all cost.
+    pub total: u128,
+}
+
 impl Game {
-    pub fn new() -> Self {
+    /// Creates a race with a random seed.
+    pub fn new(config: RaceConfig) -> Self {
         let mut rng = rand::thread_rng();
+        Self::with_seed(config, rng.gen())
+    }
+
+    /// Creates a race whose randomness all derives from `seed`, so that the same cars, config
+    /// and seed always produce the same race.
+    pub fn with_seed(config: RaceConfig, seed: u64) -> Self {
         Self {
+            config,
             state: State::Waiting,
             turns: 1,
             cars: Vec::new(),
@@ -55,75 +99,469 @@ impl Game {
             logs: Vec::new(),
             winner: None,
             actions_sold: vec![0; 3],
-            seed: rng.gen(),
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            index: 0,
+            turn_seed: 0,
+            purchases: Vec::new(),
+            time_budget: None,
+            deadline: None,
+            log_sink: None,
+        }
+    }
+
+    /// Rebuilds a race from its log, without the cars' programs.
+    ///
+    /// Starting from the state of the log's first turn, every turn's penalties and purchases are
+    /// applied again, and the state they lead to is checked against the state the log recorded
+    /// for the next turn, then against the log's result. The first difference is returned as a
+    /// [`GameError::Divergence`].
+    pub fn from_log(log: RaceLog) -> Result<Self, GameError> {
+        let RaceLog {
+            header,
+            turns,
+            result,
+        } = log;
+        let Some(first) = turns.first() else {
+            return Err(GameError::Divergence {
+                turn: 0,
+                message: "the log has no turns".to_string(),
+            });
+        };
+
+        let mut game = Self::with_seed(header.config, header.seed);
+        game.state = State::Active;
+        game.turns = first.turn;
+        game.cars = first.cars.clone();
+        game.bananas = first.bananas.clone();
+        game.actions_sold = first.actions_sold.clone();
+
+        let names: Vec<String> = game.cars.iter().map(|car| car.name.clone()).collect();
+        if names != header.cars {
+            return Err(game.divergence(format!(
+                "the cars are {:?} in the header but {:?} in the first turn",
+                header.cars, names
+            )));
+        }
+
+        for entry in &turns {
+            if game.state != State::Active {
+                return Err(game.divergence("the race is over but the log goes on".to_string()));
+            }
+            game.check(entry.turn, &entry.cars, &entry.bananas, &entry.actions_sold)?;
+            let prices = game.prices();
+            if entry.costs != prices {
+                return Err(game.differs("prices", &entry.costs, &prices));
+            }
+            game.replay_turn(entry)?;
+        }
+
+        game.check(
+            result.turn,
+            &result.cars,
+            &result.bananas,
+            &result.actions_sold,
+        )?;
+        if result.winner != game.winner {
+            return Err(game.differs("the winner", &result.winner, &game.winner));
+        }
+        if game.state == State::Active {
+            return Err(game.divergence("the log ends before the race does".to_string()));
+        }
+
+        game.logs = turns;
+        Ok(game)
+    }
+
+    /// Plays the turn `entry` recorded again: its penalties, then its purchases, each car making
+    /// them on its own copy of the race.
+    fn replay_turn(&mut self, entry: &Log) -> Result<(), GameError> {
+        self.log_turn();
+
+        for event in &entry.events {
+            let (car, error) = match event {
+                Event::BudgetExceeded {
+                    car,
+                    budget,
+                    penalty,
+                    ..
+                } => (
+                    *car,
+                    GameError::BudgetExceeded {
+                        budget: *budget,
+                        penalty: *penalty,
+                    },
+                ),
+                Event::ScriptError { car, message, .. } => {
+                    (*car, GameError::Script(message.clone()))
+                }
+                _ => continue,
+            };
+            self.car(car)?;
+            self.runner_failed(car, error);
+        }
+
+        let mut states: Vec<Self> = (0..self.cars.len())
+            .map(|index| {
+                let mut state = self.clone();
+                state.index = index;
+                state
+            })
+            .collect();
+        for purchase in &entry.purchases {
+            let index = purchase.action.car();
+            let car = self.car(index)?;
+            let state = &mut states[index];
+            let bought = !car.disqualified
+                && match purchase.action {
+                    Action::Acceleration { amount, .. } => state.buy_acceleration(amount),
+                    Action::Banana { .. } => state.buy_banana(),
+                    Action::Shell { amount, .. } => state.buy_shell(amount),
+                };
+            if !bought {
+                return Err(self.divergence(format!(
+                    "car {} ({}) can't buy {:?}",
+                    index, car.name, purchase.action
+                )));
+            }
+        }
+
+        self.settle(&states);
+
+        let replayed = self.logs.pop().expect("log_turn pushed an entry");
+        if replayed.purchases != entry.purchases {
+            return Err(GameError::Divergence {
+                turn: entry.turn,
+                message: format!(
+                    "the purchases are {:?} in the log but {:?} when replayed",
+                    entry.purchases, replayed.purchases
+                ),
+            });
+        }
+        if replayed.actions != entry.actions {
+            return Err(GameError::Divergence {
+                turn: entry.turn,
+                message: format!(
+                    "the actions are {:?} in the log but {:?} when replayed",
+                    entry.actions, replayed.actions
+                ),
+            });
         }
+        if replayed.events != entry.events {
+            return Err(GameError::Divergence {
+                turn: entry.turn,
+                message: format!(
+                    "the events are {:?} in the log but {:?} when replayed",
+                    entry.events, replayed.events
+                ),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn car(&self, index: usize) -> Result<&Car, GameError> {
+        self.cars
+            .get(index)
+            .ok_or_else(|| self.divergence(format!("there is no car {}", index)))
+    }
+
+    /// Checks that the race is in the state a log recorded.
+    fn check(
+        &self,
+        turn: usize,
+        cars: &[Car],
+        bananas: &[u32],
+        actions_sold: &[u128],
+    ) -> Result<(), GameError> {
+        if turn != self.turns {
+            return Err(self.differs("the turn", &turn, &self.turns));
+        }
+        if cars.len() != self.cars.len() {
+            return Err(self.differs("the number of cars", &cars.len(), &self.cars.len()));
+        }
+        for (index, (logged, car)) in cars.iter().zip(&self.cars).enumerate() {
+            let fields: [(&str, &dyn Debug, &dyn Debug); 4] = [
+                ("balance", &logged.balance, &car.balance),
+                ("speed", &logged.speed, &car.speed),
+                ("y", &logged.y, &car.y),
+                ("disqualified", &logged.disqualified, &car.disqualified),
+            ];
+            for (field, logged, replayed) in fields {
+                if format!("{:?}", logged) != format!("{:?}", replayed) {
+                    let what = format!("car {} ({})'s {}", index, car.name, field);
+                    return Err(self.differs(&what, logged, replayed));
+                }
+            }
+        }
+        if bananas != self.bananas {
+            return Err(self.differs("bananas", &bananas, &self.bananas));
+        }
+        if actions_sold != self.actions_sold {
+            return Err(self.differs("actions sold", &actions_sold, &self.actions_sold));
+        }
+
+        Ok(())
     }
 
-    pub fn register(&mut self, car: Car) {
-        assert!(!(self.state != State::Waiting), "Game already started");
+    fn differs(&self, what: &str, logged: &dyn Debug, replayed: &dyn Debug) -> GameError {
+        self.divergence(format!(
+            "{} is {:?} in the log but {:?} when replayed",
+            what, logged, replayed
+        ))
+    }
+
+    fn divergence(&self, message: String) -> GameError {
+        GameError::Divergence {
+            turn: self.turns,
+            message,
+        }
+    }
 
+    /// Streams the race's log to `sink` as it is played, one [`Record`] per line: the header as
+    /// the race starts, a record after each turn and the result once the race is done.
+    pub fn with_log_sink(mut self, sink: impl Write + Send + 'static) -> Self {
+        self.log_sink = Some(LogSink::new(sink));
+        self
+    }
+
+    /// Gives every car `limit` of wall-clock time to decide each turn. A car that takes longer
+    /// is disqualified and its runner is dropped once it stops: Lua scripts are stopped at the
+    /// deadline and WASM modules once they run out of fuel, but native strategies run until they
+    /// return. Timing depends on the machine, so races with a time budget may not be reproducible.
+    pub fn with_time_budget(mut self, limit: Duration) -> Self {
+        self.time_budget = Some(limit);
+        self
+    }
+
+    /// Adds a car to the race, with the race's starting balance. Cars are seated in the order
+    /// they register.
+    pub fn register(&mut self, mut car: Car) -> Result<(), GameError> {
+        if self.state != State::Waiting {
+            return Err(GameError::AlreadyStarted);
+        }
+        if self.cars.len() >= self.config.max_players {
+            return Err(GameError::RaceFull {
+                max_players: self.config.max_players,
+            });
+        }
+
+        car.balance = self.config.starting_balance.into();
         self.cars.push(car);
-        if self.cars.len() == PLAYERS_REQUIRED {
-            self.state = State::Active;
+
+        Ok(())
+    }
+
+    /// Closes registration, checking that the race has between `min_players` and
+    /// `max_players` cars.
+    pub fn start(&mut self) -> Result<(), GameError> {
+        if self.state != State::Waiting {
+            return Err(GameError::AlreadyStarted);
+        }
+        if !(self.config.min_players..=self.config.max_players).contains(&self.cars.len()) {
+            return Err(GameError::PlayerCount {
+                min: self.config.min_players,
+                max: self.config.max_players,
+                registered: self.cars.len(),
+            });
         }
+
+        self.state = State::Active;
+
+        Ok(())
     }
 
-    pub fn race(&mut self) {
+    /// Plays the race to the end. Cars whose turn fails only lose that turn, with the failure
+    /// recorded in the log.
+    pub fn race(&mut self) -> Result<(), GameError> {
         if self.state != State::Active {
-            panic!("Not enough players to start the game");
+            return Err(GameError::NotStarted);
         }
 
-        self.log_turn();
-
+        self.stream(|game| Record::Header(game.header()))?;
         while self.state == State::Active {
             self.play_turn();
+            self.stream(|game| Record::Turn(game.logs.last().unwrap().clone()))?;
         }
+        self.stream(|game| Record::Result(game.outcome()))
+    }
 
-        self.export_log();
+    /// Writes the record `record` makes to the log sink, if there is one.
+    fn stream(&self, record: impl FnOnce(&Self) -> Record) -> Result<(), GameError> {
+        let Some(sink) = &self.log_sink else {
+            return Ok(());
+        };
+        sink.write(&record(self))
+            .map_err(|e| GameError::Log(e.to_string()))
+    }
+
+    /// The car that won, once the race is done. Nobody wins a race where every car got
+    /// disqualified, or that went on for `max_turns` turns.
+    pub fn winner(&self) -> Option<&Car> {
+        self.winner.map(|index| &self.cars[index])
+    }
+
+    /// The cars' indexes from first to last: the winner, then the others by distance covered,
+    /// ties going to the first seated.
+    pub fn standings(&self) -> Vec<usize> {
+        let mut standings: Vec<usize> = (0..self.cars.len()).collect();
+        standings.sort_by_key(|&index| (self.winner != Some(index), Reverse(self.cars[index].y)));
+        standings
     }
 
     fn play_turn(&mut self) {
-        assert!(self.state == State::Active, "Game not active");
+        self.log_turn();
 
-        // cars choose their actions
+        // every car gets a seed of its own each turn, disqualified or not, so that the seeds
+        // only depend on the race's seed, the turn and the car
+        let turn_seeds: Vec<u64> = self.cars.iter().map(|_| self.rng.gen()).collect();
+
+        // cars choose their actions at the same time, each on its own thread and copy of the race
+        let (sender, receiver) = mpsc::channel();
+        let deadline = self.time_budget.map(|limit| Instant::now() + limit);
+        let mut states: Vec<Option<Self>> = Vec::with_capacity(self.cars.len());
+        for (index, &turn_seed) in turn_seeds.iter().enumerate() {
+            let mut game = self.clone();
+            game.index = index;
+            game.turn_seed = turn_seed;
+            game.deadline = deadline;
+
+            let car = &mut self.cars[index];
+            let runner = if car.disqualified {
+                None
+            } else {
+                car.runner.take()
+            };
+            match runner {
+                Some(mut runner) => {
+                    states.push(None);
+
+                    let sender = sender.clone();
+                    thread::spawn(move || {
+                        let result =
+                            panic::catch_unwind(AssertUnwindSafe(|| runner.run(&mut game)))
+                                .unwrap_or_else(|_| {
+                                    Err(GameError::Script("Runner panicked".to_string()))
+                                });
+                        let _ = sender.send((index, runner, game, result, Instant::now()));
+                    });
+                }
+                None => {
+                    states.push(Some(game));
+                }
+            }
+        }
+        drop(sender);
+
+        let mut results: Vec<Option<(Self, Result<(), GameError>)>> =
+            (0..self.cars.len()).map(|_| None).collect();
+        loop {
+            let received = match deadline {
+                Some(deadline) => {
+                    receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                }
+                None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            let Ok((index, runner, game, result, finished)) = received else {
+                break;
+            };
+            // a runner that stopped itself at the deadline, such as a Lua script, timed out all
+            // the same, whatever it returned
+            if deadline.is_some_and(|deadline| finished >= deadline) {
+                continue;
+            }
 
-        let states: Vec<Self> = (0..self.cars.len())
-            .map(|index| {
-                let mut game = self.clone();
-                game.run_car_script(index);
-                game
-            })
-            .collect();
+            self.cars[index].runner = Some(runner);
+            results[index] = Some((game, result));
+        }
 
-        // update the number of actions sold
-        states.iter().for_each(|state| {
-            for i in 0..state.actions_sold.len() {
-                self.actions_sold[i] += state.actions_sold[i];
+        // handle the cars' outcomes in seat order, whatever order they finished in
+        for (index, result) in results.into_iter().enumerate() {
+            if states[index].is_some() {
+                continue;
             }
-        });
 
-        // apply actions to state
-        states.iter().enumerate().for_each(|(index_car, state)| {
-            if state.actions_sold[Action::Banana(0).into_usize()] > 0 {
+            let result = match result {
+                Some((game, Ok(()))) => {
+                    states[index] = Some(game);
+                    continue;
+                }
+                Some((_, Err(e))) => e,
+                // the runner is dropped when its thread stops, which can't be waited for
+                None => GameError::BudgetExceeded {
+                    budget: Budget::Time(self.time_budget.expect("only time budgets time out")),
+                    penalty: Penalty::Disqualify,
+                },
+            };
+
+            self.runner_failed(index, result);
+
+            let mut game = self.clone();
+            game.index = index;
+            game.turn_seed = turn_seeds[index];
+            states[index] = Some(game);
+        }
+        let states: Vec<Self> = states.into_iter().flatten().collect();
+
+        self.settle(&states);
+    }
+
+    /// Charges every car what it bought on its copy of the race, `states[car]`, records the
+    /// purchases and plays the turn out.
+    fn settle(&mut self, states: &[Self]) {
+        let mut bought = vec![vec![0u128; 3]; self.cars.len()];
+        for (index, state) in states.iter().enumerate() {
+            self.cars[index].balance = state.cars[index].balance;
+            for purchase in &state.purchases {
+                let bought = &mut bought[index][purchase.action.into_usize()];
+                *bought = bought.saturating_add(purchase.action.amount() as u128);
+            }
+            self.logs
+                .last_mut()
+                .unwrap()
+                .purchases
+                .extend_from_slice(&state.purchases);
+        }
+
+        self.resolve_turn(&bought);
+    }
+
+    /// Plays out a turn where each car bought `bought[car][item]` items: applies the items and
+    /// moves the cars in seat order, until a car crosses the finish line and wins.
+    fn resolve_turn(&mut self, bought: &[Vec<u128>]) {
+        for car_bought in bought {
+            for (sold, &bought) in self.actions_sold.iter_mut().zip(car_bought) {
+                *sold = sold.saturating_add(bought);
+            }
+        }
+
+        // apply actions to state, a car dropping one banana however many it bought, and firing
+        // and speeding up by no more than a u32 holds
+        let applied = |bought: u128| u32::try_from(bought).unwrap_or(u32::MAX);
+        for (index_car, bought) in bought.iter().enumerate() {
+            if bought[Action::BANANA] > 0 {
                 self.apply_banana(index_car);
             }
-            if state.actions_sold[Action::Shell(0).into_usize()] > 0 {
-                let amount = state.actions_sold[Action::Shell(0).into_usize()];
-                self.apply_shell(amount as u32, index_car);
+            if bought[Action::SHELL] > 0 {
+                self.apply_shell(applied(bought[Action::SHELL]), index_car);
             }
-            if state.actions_sold[Action::Acceleration(0).into_usize()] > 0 {
-                let amount = state.actions_sold[Action::Acceleration(0).into_usize()];
-                self.apply_acceleration(amount as u32, index_car);
+            if bought[Action::ACCELERATION] > 0 {
+                self.apply_acceleration(applied(bought[Action::ACCELERATION]), index_car);
             }
-        });
+        }
 
         // update y position and execute actions
-        for (index, car) in self.cars.iter_mut().enumerate() {
+        for index in 0..self.cars.len() {
+            let car = &mut self.cars[index];
+            if car.disqualified {
+                continue;
+            }
+
             // Move car
             let car_old_position = car.y;
-            let car_new_position = car.y + car.speed;
+            let car_new_position = car.y.saturating_add(car.speed);
 
-            car.y += car.speed;
+            car.y = car_new_position;
 
             // Check for banana collisions
             if let Some(pos) = self
@@ -132,112 +570,150 @@ impl Game {
                 .position(|&b| car_old_position < b && car_new_position >= b)
             {
                 car.speed = 0;
-                car.y = self.bananas[pos];
-                self.bananas.remove(pos);
+                car.y = self.bananas.remove(pos);
+
+                let event = Event::Collision {
+                    turn: self.turns,
+                    car: index,
+                    banana: car.y,
+                };
+                self.log_event(event);
             }
 
             // Check for finish line
-            if car.y >= FINISH_DISTANCE {
+            if self.cars[index].y >= self.config.finish_distance {
                 self.state = State::Done;
                 self.winner = Some(index);
                 break;
             }
         }
 
-        self.log_turn();
+        if self.cars.iter().all(|car| car.disqualified) {
+            self.state = State::Done;
+        }
 
-        self.turns += 1;
-    }
+        // a race where no car gets there ends as a draw
+        if self.turns >= self.config.max_turns {
+            self.state = State::Done;
+        }
 
-    fn log_turn(&mut self) {
-        let index = self.get_index();
-        if self.logs.is_empty() {
-            self.logs.push(Log::default());
-        }
-
-        let prices = vec![
-            self.get_accelerate_cost(1),
-            self.get_banana_cost(),
-            self.get_shell_cost(1),
-        ];
-
-        self.logs.last_mut().unwrap().add_info(
-            self.bananas.clone(),
-            prices,
-            index,
-            self.cars.clone(),
-            self.actions_sold.clone(),
-        );
+        self.turns += 1;
     }
 
-    fn run_car_script(&mut self, index: usize) {
-        self.logs.push(Log::default());
-        let lua = Lua::new();
-        let car: Car = self.cars[index].clone();
-
-        let globals = lua.globals();
-
-        let state = Arc::new(Mutex::new(self.to_owned()));
-        let game_state = GameState(state.clone());
-
-        lua.globals().set("GameState", game_state).unwrap();
-
-        lua.load(&car.lua_script)
-            .exec()
-            .expect("Failed to load Lua script");
-
-        let take_your_turn: Result<Function, mlua::prelude::LuaError> = globals.get("takeYourTurn");
-
-        if let Err(e) = take_your_turn {
-            println!("Erroron getting take_your_turn function: {:?}", e);
-            return;
-        }
+    /// Applies the outcome of a car's failed turn and records it in the log.
+    fn runner_failed(&mut self, index: usize, error: GameError) {
+        let event = match error {
+            GameError::BudgetExceeded { budget, penalty } => {
+                if penalty == Penalty::Disqualify {
+                    let car = &mut self.cars[index];
+                    car.disqualified = true;
+                    car.speed = 0;
+                }
 
-        let result: Result<(), mlua::prelude::LuaError> = take_your_turn.unwrap().call(());
+                Event::BudgetExceeded {
+                    turn: self.turns,
+                    car: index,
+                    budget,
+                    penalty,
+                }
+            }
+            error => Event::ScriptError {
+                turn: self.turns,
+                car: index,
+                message: error.to_string(),
+            },
+        };
+
+        self.log_event(event);
+    }
 
-        if let Ok(()) = result {
-            let new_state = state.lock().unwrap();
-            //self.cars.clone_from(&new_state.cars);
-            //self.bananas.clone_from(&new_state.bananas);
-            //self.logs.clone_from(&new_state.logs);
-            self.actions_sold.clone_from(&new_state.actions_sold);
-        } else {
-            println!("Error on calling take_your_turn function: {:?}", result);
-        }
+    fn log_event(&mut self, event: Event) {
+        self.logs.last_mut().unwrap().events.push(event);
     }
 
-    fn buy_acceleration(&mut self, car_index: usize, amount: u32) -> bool {
-        let cost = self.get_accelerate_cost(amount);
-        let car = self.cars.get_mut(car_index).expect("Car failed");
-        if car.balance >= cost {
-            car.balance -= cost;
+    /// Starts the log entry of the turn about to be played.
+    fn log_turn(&mut self) {
+        self.purchases.clear();
+        let entry = Log {
+            turn: self.turns,
+            purchases: Vec::new(),
+            actions: Vec::new(),
+            bananas: self.bananas.clone(),
+            costs: self.prices(),
+            cars: self.cars.iter().map(Car::snapshot).collect(),
+            actions_sold: self.actions_sold.clone(),
+            events: Vec::new(),
+        };
+        self.logs.push(entry);
+    }
 
-            self.actions_sold[Action::Acceleration(0).into_usize()] += amount as u128;
+    /// Prices of one acceleration, one banana and one shell, None when unaffordable.
+    fn prices(&self) -> Vec<Option<u128>> {
+        [Action::ACCELERATION, Action::BANANA, Action::SHELL]
+            .into_iter()
+            .map(|item| self.cost(item, 1, u128::MAX))
+            .collect()
+    }
 
-            return true;
+    /// Charges the car whose turn is being played for `amount` accelerations, returning whether
+    /// it could afford them. The car speeds up when the turn is played.
+    pub fn buy_acceleration(&mut self, amount: u32) -> bool {
+        let car_index = self.index;
+        let Some(cost) = self.affordable(car_index, Action::ACCELERATION, amount) else {
+            return false;
+        };
+        self.cars[car_index].balance -= cost;
+
+        let sold = &mut self.actions_sold[Action::ACCELERATION];
+        *sold = sold.saturating_add(amount as u128);
+        if amount > 0 {
+            self.purchases.push(Purchase {
+                action: Action::Acceleration {
+                    car: car_index,
+                    amount,
+                },
+                cost,
+            });
         }
 
-        false
+        true
     }
 
     fn apply_acceleration(&mut self, amount: u32, car_index: usize) {
         let car = &mut self.cars[car_index];
-        car.speed += amount;
+        car.speed = car.speed.saturating_add(amount);
 
         self.logs
             .last_mut()
             .unwrap()
             .actions
-            .push(Action::Acceleration(amount));
+            .push(Action::Acceleration {
+                car: car_index,
+                amount,
+            });
     }
 
-    fn buy_banana(&mut self, car_index: usize) -> bool {
-        let cost = self.get_banana_cost();
+    /// Charges the car whose turn is being played for a banana, returning whether it could
+    /// afford it and there is no banana where it stands yet. The banana is dropped when the turn
+    /// is played.
+    pub fn buy_banana(&mut self) -> bool {
+        let car_index = self.index;
+        let Some(cost) = self.affordable(car_index, Action::BANANA, 1) else {
+            return false;
+        };
         let car = &mut self.cars[car_index];
-        if car.balance >= cost && !self.bananas.contains(&car.y) {
+        if !self.bananas.contains(&car.y) {
             car.balance -= cost;
 
-            self.actions_sold[Action::Banana(0).into_usize()] += 1;
+            let sold = &mut self.actions_sold[Action::BANANA];
+            *sold = sold.saturating_add(1);
+            self.purchases.push(Purchase {
+                action: Action::Banana { car: car_index },
+                cost,
+            });
+            // the car sees its banana for the rest of its turn, and can't pay for a second one
+            self.bananas.push(car.y);
+            self.bananas.sort();
 
             return true;
         }
@@ -254,222 +730,755 @@ impl Game {
             .last_mut()
             .unwrap()
             .actions
-            .push(Action::Banana(car_index));
+            .push(Action::Banana { car: car_index });
     }
 
-    fn buy_shell(&mut self, car_index: usize, amount: u32) -> bool {
-        let cost = self.get_shell_cost(amount);
-        let car = &mut self.cars.get_mut(car_index).unwrap();
-
-        if car.balance < cost {
+    /// Charges the car whose turn is being played for `amount` shells, returning whether it
+    /// could afford them. The shells are fired when the turn is played.
+    pub fn buy_shell(&mut self, amount: u32) -> bool {
+        let car_index = self.index;
+        let Some(cost) = self.affordable(car_index, Action::SHELL, amount) else {
             return false;
+        };
+
+        self.cars[car_index].balance -= cost;
+        let sold = &mut self.actions_sold[Action::SHELL];
+        *sold = sold.saturating_add(amount as u128);
+        if amount > 0 {
+            self.purchases.push(Purchase {
+                action: Action::Shell {
+                    car: car_index,
+                    amount,
+                },
+                cost,
+            });
         }
 
-        car.balance -= cost;
-        self.actions_sold[Action::Shell(0).into_usize()] += amount as u128;
-
         true
     }
 
     fn apply_shell(&mut self, amount: u32, car_index: usize) {
-        let cars = self.cars.clone();
+        self.logs.last_mut().unwrap().actions.push(Action::Shell {
+            car: car_index,
+            amount,
+        });
 
-        // lets enum the cars with their index and remove the current car
-        let mut remaining_cars = cars.into_iter().enumerate().collect::<Vec<_>>();
-        remaining_cars.remove(car_index);
+        let y = self.cars[car_index].y;
 
-        // lets filter the cars that are in front of the current car
-        let mut cars_in_front: Vec<_> = remaining_cars
+        // the closest adversary in front of the current car, if any
+        let target = self
+            .cars
             .iter()
-            .filter(|(_, adversary_car)| self.cars[car_index].y <= adversary_car.y)
-            .collect();
+            .enumerate()
+            .filter(|&(index, adversary_car)| index != car_index && y <= adversary_car.y)
+            .min_by_key(|(_, adversary_car)| adversary_car.y)
+            .map(|(index, adversary_car)| (index, adversary_car.y));
+
+        // each shell stops a banana on the way or the target, the ones left over hit nothing
+        let target_y = target.map_or(u32::MAX, |(_, target_y)| target_y);
+        let hittable = self
+            .bananas
+            .iter()
+            .filter(|&&b| b > y && b <= target_y)
+            .count()
+            + target.is_some() as usize;
+        for _ in 0..(amount as usize).min(hittable) {
+            // the shell hits the first banana between the current car and its target instead
+            if let Some(pos) = self.bananas.iter().position(|&b| b > y && b <= target_y) {
+                let banana = self.bananas.remove(pos);
+                self.log_event(Event::ShellIntercepted {
+                    turn: self.turns,
+                    car: car_index,
+                    banana,
+                });
+                continue;
+            }
 
-        // lets sort the cars by their y position
-        cars_in_front.sort_by(|(_, a), (_, b)| a.y.cmp(&b.y));
+            //// lets hit the first car in front of the current car with a shell removing its speed
+            if let Some((index, _)) = target {
+                self.cars[index].speed = 0;
+                self.log_event(Event::ShellHit {
+                    turn: self.turns,
+                    car: car_index,
+                    target: index,
+                });
+            }
+        }
+    }
 
-        for _ in 0..amount {
-            if !self.bananas.is_empty() {
-                let b = self.bananas.clone();
+    /// What `amount` accelerations cost right now, or `u128::MAX` when they're unaffordable
+    /// whatever the balance: one of them can't be sold or priced, or their total doesn't fit in
+    /// a `u128` or goes through more than [`MAX_PRICES`](crate::pricing::MAX_PRICES) prices.
+    pub fn get_accelerate_cost(&self, amount: u32) -> u128 {
+        self.cost(Action::ACCELERATION, amount, u128::MAX)
+            .unwrap_or(u128::MAX)
+    }
 
-                let bananas: Vec<_> = b
-                    .iter()
-                    .enumerate()
-                    .filter(|&(_, b)| *b > self.cars[car_index].y && *b <= cars_in_front[0].1.y)
-                    .collect();
+    /// What a banana costs right now, or `u128::MAX` when it's unaffordable whatever the balance.
+    pub fn get_banana_cost(&self) -> u128 {
+        self.cost(Action::BANANA, 1, u128::MAX).unwrap_or(u128::MAX)
+    }
 
-                if !bananas.is_empty() {
-                    let pos = bananas.first().unwrap().0;
-                    self.bananas.remove(pos);
-                    continue;
-                }
-            }
+    /// What `amount` shells cost right now, or `u128::MAX` when they're unaffordable whatever the
+    /// balance, as for [`get_accelerate_cost`](Self::get_accelerate_cost).
+    pub fn get_shell_cost(&self, amount: u32) -> u128 {
+        self.cost(Action::SHELL, amount, u128::MAX)
+            .unwrap_or(u128::MAX)
+    }
 
-            //// lets hit the first car in front of the current car with a shell removing its speed
-            if let Some((index, _)) = cars_in_front.first() {
-                self.cars[*index].speed = 0;
+    /// What the car whose turn is being played would pay for `accelerations`, a banana if
+    /// `banana` and `shells`, bought in that order right now. Each cost is `u128::MAX` when the
+    /// car can't buy the items with what the ones before leave of its balance, and so is the
+    /// total then.
+    pub fn quote(&self, accelerations: u32, banana: bool, shells: u32) -> Quote {
+        let car = self.cars.get(self.index);
+        let mut balance = car.map_or(0, |car| car.balance);
+        let mut buy = |item, amount| match self.cost(item, amount, balance) {
+            Some(cost) => {
+                balance -= cost;
+                cost
             }
+            None => u128::MAX,
+        };
+        let acceleration = buy(Action::ACCELERATION, accelerations);
+        // as when buying, there can only be one banana where the car stands
+        let banana = if banana && car.is_some_and(|car| self.bananas.contains(&car.y)) {
+            u128::MAX
+        } else {
+            buy(Action::BANANA, banana as u32)
+        };
+        let shell = buy(Action::SHELL, shells);
+        Quote {
+            acceleration,
+            banana,
+            shell,
+            total: acceleration.saturating_add(banana).saturating_add(shell),
         }
     }
 
-    fn get_accelerate_cost(&self, amount: u32) -> u128 {
-        dbg!(&self.actions_sold);
-        dbg!(&self.turns);
-        dbg!(&self.actions_sold[Action::Acceleration(0).into_usize()]);
-        dbg!(amount);
-        let actions_sold = self.actions_sold[Action::Acceleration(0).into_usize()];
-        let mut sum = 0;
-        for i in 0..amount {
-            sum += Self::compute_action_price(
-                ACCELERATE_TARGET_PRICE as f64,
-                ACCELERATE_PER_TURN_DECREASE,
+    /// The most accelerations `budget` buys right now, counting no more of it than the balance
+    /// of the car whose turn is being played.
+    pub fn max_affordable_acceleration(&self, budget: u128) -> u32 {
+        let balance = self.cars.get(self.index).map_or(0, |car| car.balance);
+        self.config
+            .pricing(Action::ACCELERATION)
+            .model()
+            .affordable(
                 self.turns as u64,
-                actions_sold + i as u128,
-                ACCELERATE_SELL_PER_TURN as f64,
-            ) as u128;
-        }
-
-        sum
+                self.actions_sold[Action::ACCELERATION],
+                budget.min(balance),
+            )
     }
 
-    fn get_banana_cost(&self) -> u128 {
-        let actions_sold = self.actions_sold[Action::Banana(0).into_usize()];
-        Self::compute_action_price(
-            BANANA_TARGET_PRICE as f64,
-            BANANA_PER_TURN_DECREASE,
+    /// What `amount` of the item cost, or None when they cost more than `budget` or one of them
+    /// is unaffordable.
+    fn cost(&self, item: usize, amount: u32, budget: u128) -> Option<u128> {
+        self.config.pricing(item).model().cost(
             self.turns as u64,
-            actions_sold,
-            BANANA_SELL_PER_TURN,
-        ) as u128
-    }
-
-    fn get_shell_cost(&self, amount: u32) -> u128 {
-        let actions_sold = self.actions_sold[Action::Shell(0).into_usize()];
-        let mut sum = 0;
-        for i in 0..amount {
-            sum += Self::compute_action_price(
-                SHELL_TARGET_PRICE as f64,
-                SHELL_PER_TURN_DECREASE,
-                self.turns as u64,
-                actions_sold + i as u128,
-                SHELL_SELL_PER_TURN,
-            ) as u128;
-        }
+            self.actions_sold[item],
+            amount,
+            budget,
+        )
+    }
 
-        sum
+    /// What the car pays for `amount` of the item, or None when there's no such car or it can't
+    /// afford them.
+    fn affordable(&self, car_index: usize, item: usize, amount: u32) -> Option<u128> {
+        let balance = self.cars.get(car_index)?.balance;
+        self.cost(item, amount, balance)
     }
 
-    fn compute_action_price(
-        target_price: f64,
-        per_turn_price_decrease: f64,
-        turns_since_start: u64,
-        sold: u128,
-        sell_per_turn: f64,
-    ) -> f64 {
-        // Compute the intermediate value
-        let intermediate_value =
-            (turns_since_start - 1) as f64 - ((sold + 1) as f64 / sell_per_turn);
+    /// The index of the car whose turn is being played.
+    pub fn get_index(&self) -> usize {
+        self.index
+    }
 
-        // Compute the price multiplier using exponential and logarithmic functions
+    /// Where the race is at.
+    pub fn state(&self) -> &State {
+        &self.state
+    }
 
-        let pre_ln = 1.0 - per_turn_price_decrease;
-        let ln = pre_ln.ln();
-        let price_multiplier = (ln * intermediate_value).exp();
+    /// The rules of the race.
+    pub fn config(&self) -> &RaceConfig {
+        &self.config
+    }
 
-        // Compute the action price
+    /// The turn being played, starting at 1.
+    pub fn turns(&self) -> usize {
+        self.turns
+    }
 
-        target_price * price_multiplier
+    /// The cars, in the order they registered.
+    pub fn cars(&self) -> &[Car] {
+        &self.cars
     }
 
-    fn get_index(&self) -> usize {
-        self.turns % self.cars.len()
+    /// Positions of the bananas on the track, in increasing order.
+    pub fn bananas(&self) -> &[u32] {
+        &self.bananas
     }
 
-    pub fn export_log(&self) {
-        let json = serde_json::json!({"logs" : self.logs, "winner" :{"id": self.winner, "name": self.cars[self.winner.unwrap()].name}});
+    /// The race's seed.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
 
-        println!("{}", json);
+    /// The seed of the car whose turn is being played, drawn for this car and turn from the
+    /// race's seed.
+    pub fn turn_seed(&self) -> u64 {
+        self.turn_seed
+    }
 
-        let time_now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+    /// When the car whose turn is being played must have decided it by, in races with a time
+    /// budget. Runners that can be interrupted should stop there: the car is out of the race.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
 
-        let filename = format!("logs/logs_{}.json", time_now);
+    /// The race's log.
+    pub fn logs(&self) -> &[Log] {
+        &self.logs
+    }
 
-        File::create(filename)
-            .unwrap()
-            .write_all(json.to_string().as_bytes())
-            .unwrap();
+    /// What the race was set up with, the start of its log.
+    pub fn header(&self) -> Header {
+        Header {
+            version: LOG_VERSION,
+            config: self.config.clone(),
+            seed: self.seed,
+            cars: self.cars.iter().map(|car| car.name.clone()).collect(),
+        }
     }
-}
 
-struct GameState(Arc<Mutex<Game>>);
+    /// The race as it stands, the end of its log once it is done.
+    pub fn outcome(&self) -> Outcome {
+        Outcome {
+            turn: self.turns,
+            cars: self.cars.iter().map(Car::snapshot).collect(),
+            bananas: self.bananas.clone(),
+            actions_sold: self.actions_sold.clone(),
+            winner: self.winner,
+        }
+    }
 
-impl UserData for GameState {
-    fn add_fields<'lua, F: mlua::prelude::LuaUserDataFields<'lua, Self>>(fields: &mut F) {
-        fields.add_field_method_get("turns", |_, this| Ok(this.0.lock().unwrap().turns));
-        fields.add_field_method_get("cars", |_, this| Ok(this.0.lock().unwrap().cars.clone()));
-        fields.add_field_method_get("bananas", |_, this| {
-            Ok(this.0.lock().unwrap().bananas.clone())
-        });
-        fields.add_field_method_get("index", |_, this| {
-            let index = this.0.lock().unwrap().get_index() + 1;
-            Ok(index)
-        });
-        fields.add_field_method_get("seed", |_, this| {
-            let seed = this.0.lock().unwrap().seed;
-            Ok(seed)
-        });
-        fields.add_field_method_get("logs", |_, this| {
-            let logs = this.0.lock().unwrap().logs.clone();
-            Ok(logs)
-        });
+    /// The race's whole log.
+    pub fn race_log(&self) -> RaceLog {
+        RaceLog {
+            header: self.header(),
+            turns: self.logs.clone(),
+            result: self.outcome(),
+        }
     }
-    fn add_methods<'lua, M: mlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
-        methods.add_method_mut("buy_acceleration", |_, user_data, amount: u32| {
-            let mut lock = user_data.0.lock().unwrap();
-            let index = lock.get_index();
-            let success = lock.buy_acceleration(index, amount);
-            Ok(success)
-        });
 
-        methods.add_method_mut("buy_banana", |_, user_data, (): ()| {
-            let mut lock = user_data.0.lock().unwrap();
-            let index = lock.get_index();
-            let success = lock.buy_banana(index);
-            Ok(success)
-        });
+    /// The race log, as written by `export_log`.
+    pub fn log_json(&self) -> serde_json::Value {
+        serde_json::to_value(self.race_log()).expect("logs serialize to JSON")
+    }
 
-        methods.add_method_mut("buy_shell", |_, user_data, amount: u32| {
-            let mut lock = user_data.0.lock().unwrap();
-            let index = lock.get_index();
-            let success = lock.buy_shell(index, amount);
-            Ok(success)
-        });
+    /// Writes the race log as JSON to `path`, or to `logs/logs_<timestamp>.json` when no path
+    /// is given, and returns the path that was written.
+    pub fn export_log(&self, path: Option<&Path>) -> std::io::Result<PathBuf> {
+        let json = self.log_json();
 
-        methods.add_method("get_accelerate_cost", |_, user_data, amount: u32| {
-            let lock = user_data.0.lock().unwrap();
-            let cost = lock.get_accelerate_cost(amount);
-            Ok(cost)
-        });
+        let filename = match path {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let time_now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs();
 
-        methods.add_method("get_banana_cost", |_, user_data, (): ()| {
-            let lock = user_data.0.lock().unwrap();
-            let cost = lock.get_banana_cost();
-            Ok(cost)
-        });
-        methods.add_method("get_shell_cost", |_, user_data, amount: u32| {
-            let lock = user_data.0.lock().unwrap();
-            let cost = lock.get_shell_cost(amount);
-            Ok(cost)
-        });
+                PathBuf::from(format!("logs/logs_{}.json", time_now))
+            }
+        };
+
+        File::create(&filename)?.write_all(json.to_string().as_bytes())?;
+
+        Ok(filename)
     }
 }
 
+#[cfg(test)]
 mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::config::ItemPricing;
+    use crate::pricing::FixedPrice;
+    use crate::runtime::{LuaLimits, LuaRunner, Runner};
+
+    fn game_with_scripts(scripts: [&str; 3]) -> Game {
+        let mut game = Game::with_seed(RaceConfig::default(), 0);
+        for (name, script) in ["Alice", "Bob", "Charlie"].into_iter().zip(scripts) {
+            let runner = LuaRunner::new(script.to_string());
+            game.register(Car::new(Box::new(runner), name.to_string()))
+                .unwrap();
+        }
+        game.start().unwrap();
+        game
+    }
+
+    fn game() -> Game {
+        game_with_scripts(["function takeYourTurn() end"; 3])
+    }
 
     #[test]
     fn buy_actions() {
-        todo!();
+        let mut game = game();
+        let balance = game.cars[0].balance;
+
+        let cost = game.get_accelerate_cost(2);
+        assert!(game.buy_acceleration(2));
+        assert_eq!(game.cars[0].balance, balance - cost);
+        assert_eq!(game.actions_sold[Action::ACCELERATION], 2);
+
+        game.index = 1;
+        assert!(game.buy_banana());
+        assert_eq!(game.actions_sold[Action::BANANA], 1);
+
+        game.index = 2;
+        game.cars[2].balance = 0;
+        assert!(!game.buy_shell(1));
+        assert_eq!(game.actions_sold[Action::SHELL], 0);
+    }
+
+    #[test]
+    fn huge_purchases_cannot_break_the_race() {
+        let mut game = game();
+        let balance = game.cars[0].balance;
+        assert_eq!(game.get_accelerate_cost(u32::MAX), u128::MAX);
+        assert!(!game.buy_acceleration(u32::MAX));
+        assert!(!game.buy_shell(1_000_000_000));
+        assert_eq!(game.cars[0].balance, balance);
+        assert_eq!(game.actions_sold, vec![0, 0, 0]);
+
+        // free accelerations bought as fast as they go
+        let script = "function takeYourTurn() GameState:buy_acceleration(4294967295) end";
+        let mut game = game_with_scripts([script; 3]);
+        game.config.acceleration = ItemPricing::Fixed(FixedPrice { price: 0 });
+        game.play_turn();
+        game.play_turn();
+        assert_eq!(game.cars[0].speed, u32::MAX);
+        assert_eq!(game.cars[0].y, u32::MAX);
+        assert_eq!(game.get_accelerate_cost(u32::MAX), 0);
+
+        // free items bought past what a u32 holds in a turn
+        let script = r#"
+            function takeYourTurn()
+                GameState:buy_acceleration(4294967295)
+                GameState:buy_acceleration(4294967295)
+                GameState:buy_shell(4294967295)
+                GameState:buy_shell(4294967295)
+            end
+        "#;
+        let mut game = game_with_scripts([script; 3]);
+        game.config.acceleration = ItemPricing::Fixed(FixedPrice { price: 0 });
+        game.config.shell = ItemPricing::Fixed(FixedPrice { price: 0 });
+        game.play_turn();
+        // the car seated last fires at the first, and no one fires at it
+        assert_eq!(game.cars[2].speed, u32::MAX);
+        assert_eq!(
+            game.actions_sold,
+            vec![6 * u32::MAX as u128, 0, 6 * u32::MAX as u128]
+        );
+        // the cars start level, so each one's shells hit another car once
+        let hits = game.logs[0]
+            .events
+            .iter()
+            .filter(|event| matches!(event, Event::ShellHit { .. }))
+            .count();
+        assert_eq!(hits, 3);
+    }
+
+    #[test]
+    fn play_turn_charges_the_buying_car() {
+        let idle = "function takeYourTurn() end";
+        let accelerate = "function takeYourTurn() GameState:buy_acceleration(3) end";
+        let mut game = game_with_scripts([idle, accelerate, idle]);
+        let balance = game.cars[1].balance;
+        let cost = game.get_accelerate_cost(3);
+
+        game.play_turn();
+
+        assert_eq!(game.cars[0].balance, balance);
+        assert_eq!(game.cars[1].balance, balance - cost);
+        assert_eq!(game.cars[1].speed, 3);
+        assert_eq!(game.cars[1].y, 3);
+        assert_eq!(game.actions_sold, vec![3, 0, 0]);
+        assert_eq!(
+            game.logs[0].purchases,
+            vec![Purchase {
+                action: Action::Acceleration { car: 1, amount: 3 },
+                cost
+            }]
+        );
+    }
+
+    #[test]
+    fn budget_penalties() {
+        let idle = "function takeYourTurn() GameState:buy_acceleration(1) end";
+        let spin = "function takeYourTurn() GameState:buy_acceleration(1) while true do end end";
+        let mut game = game_with_scripts([idle, spin, spin]);
+        for (index, penalty) in [(1, Penalty::SkipTurn), (2, Penalty::Disqualify)] {
+            let limits = LuaLimits {
+                instructions: 10_000,
+                penalty,
+                ..LuaLimits::default()
+            };
+            let runner = LuaRunner::new(spin.to_string()).with_limits(limits);
+            game.cars[index].runner = Some(Box::new(runner));
+        }
+
+        game.play_turn();
+        game.play_turn();
+
+        assert_eq!(game.cars[0].y, 3);
+        assert_eq!(game.cars[1].y, 0);
+        assert!(!game.cars[1].disqualified);
+        assert!(game.cars[2].disqualified);
+
+        let events: Vec<&Event> = game.logs.iter().flat_map(|log| &log.events).collect();
+        assert_eq!(events.len(), 3);
+        assert!(matches!(
+            *events[1],
+            Event::BudgetExceeded {
+                turn: 1,
+                car: 2,
+                penalty: Penalty::Disqualify,
+                ..
+            }
+        ));
+
+        // replays apply the disqualification from the log
+        game.race().unwrap();
+        let replayed = Game::from_log(game.race_log()).unwrap();
+        assert_eq!(replayed.log_json(), game.log_json());
+    }
+
+    #[test]
+    fn time_budget() {
+        use crate::runtime::{NativeRunner, Purchases, Strategy, View};
+
+        struct Slow;
+
+        impl Strategy for Slow {
+            fn take_your_turn(&mut self, _view: &View, purchases: &mut Purchases) {
+                thread::sleep(Duration::from_millis(500));
+                purchases.buy_acceleration(1);
+            }
+        }
+
+        let accelerate = "function takeYourTurn() GameState:buy_acceleration(1) end";
+        let mut game = game_with_scripts([accelerate; 3]);
+        game.cars[1].runner = Some(Box::new(NativeRunner::new(|| Slow)));
+        game.time_budget = Some(Duration::from_millis(50));
+
+        let started = Instant::now();
+        game.play_turn();
+        assert!(started.elapsed() < Duration::from_millis(500));
+
+        assert_eq!(game.cars[0].speed, 1);
+        assert_eq!(game.cars[1].speed, 0);
+        assert!(game.cars[1].disqualified);
+        assert!(game.cars[1].runner.is_none());
+        assert!(matches!(
+            game.logs.last().unwrap().events[..],
+            [Event::BudgetExceeded {
+                car: 1,
+                budget: Budget::Time(_),
+                penalty: Penalty::Disqualify,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn timed_out_scripts_stop() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        /// A runner that tells whether it is running.
+        #[derive(Debug)]
+        struct Watched {
+            runner: Box<dyn Runner>,
+            running: Arc<AtomicBool>,
+        }
+
+        impl Runner for Watched {
+            fn run(&mut self, game: &mut Game) -> Result<(), GameError> {
+                self.running.store(true, Ordering::SeqCst);
+                let result = self.runner.run(game);
+                self.running.store(false, Ordering::SeqCst);
+                result
+            }
+
+            fn box_clone(&self) -> Box<dyn Runner> {
+                Box::new(Watched {
+                    runner: self.runner.box_clone(),
+                    running: self.running.clone(),
+                })
+            }
+        }
+
+        let accelerate = "function takeYourTurn() GameState:buy_acceleration(1) end";
+        let mut game = game_with_scripts([accelerate; 3]);
+        let limits = LuaLimits {
+            instructions: u64::MAX,
+            ..LuaLimits::default()
+        };
+        let forever = LuaRunner::new("function takeYourTurn() while true do end end".to_string())
+            .with_limits(limits);
+        let running = Arc::new(AtomicBool::new(false));
+        game.cars[2].runner = Some(Box::new(Watched {
+            runner: Box::new(forever),
+            running: running.clone(),
+        }));
+        game.time_budget = Some(Duration::from_millis(50));
+
+        game.play_turn();
+        assert!(game.cars[2].disqualified);
+
+        let started = Instant::now();
+        while running.load(Ordering::SeqCst) {
+            assert!(
+                started.elapsed() < Duration::from_secs(1),
+                "the script still runs"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn races_are_deterministic() {
+        let script = r#"
+            local speed = GameState.seed % 3
+            function takeYourTurn()
+                GameState:buy_acceleration((GameState.seed + math.random(0, 2) + speed) % 3)
+                if math.random() < 0.1 then
+                    GameState:buy_banana()
+                end
+            end
+        "#;
+        let race = |seed| {
+            let mut game = Game::with_seed(RaceConfig::default(), seed);
+            for name in ["Alice", "Bob", "Charlie"] {
+                let runner = LuaRunner::new(script.to_string());
+                game.register(Car::new(Box::new(runner), name.to_string()))
+                    .unwrap();
+            }
+            game.start().unwrap();
+            game.race().unwrap();
+            game.log_json().to_string()
+        };
+
+        assert_eq!(race(7), race(7));
+        assert_ne!(race(7), race(8));
+    }
+
+    fn race_with(players: usize) -> Result<Game, GameError> {
+        let script =
+            "function takeYourTurn() GameState:buy_acceleration(2) GameState:buy_shell(1) end";
+        let mut game = Game::with_seed(RaceConfig::default(), 0);
+        for index in 0..players {
+            let runner = LuaRunner::new(script.to_string());
+            game.register(Car::new(Box::new(runner), index.to_string()))?;
+        }
+        game.start()?;
+        game.race()?;
+        Ok(game)
+    }
+
+    #[test]
+    fn any_number_of_players() {
+        for players in [2, 3, 6, 8] {
+            let game = race_with(players).unwrap();
+            assert_eq!(game.state, State::Done);
+            assert!(game.winner().unwrap().y >= game.config.finish_distance);
+        }
+    }
+
+    #[test]
+    fn start_needs_enough_players() {
+        assert_eq!(
+            race_with(1).unwrap_err(),
+            GameError::PlayerCount {
+                min: 2,
+                max: 8,
+                registered: 1
+            }
+        );
+        assert_eq!(
+            race_with(9).unwrap_err(),
+            GameError::RaceFull { max_players: 8 }
+        );
+
+        let mut game = race_with(2).unwrap();
+        assert_eq!(game.start(), Err(GameError::AlreadyStarted));
+        assert_eq!(
+            Game::with_seed(RaceConfig::default(), 0).race(),
+            Err(GameError::NotStarted)
+        );
+    }
+
+    #[test]
+    fn script_errors_are_logged() {
+        let idle = "function takeYourTurn() end";
+        let broken = "function takeYourTurn() error('boom') end";
+        let mut game = game_with_scripts([idle, broken, idle]);
+
+        game.play_turn();
+
+        let events = &game.logs.last().unwrap().events;
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            &events[0],
+            Event::ScriptError { turn: 1, car: 1, message } if message.contains("boom")
+        ));
+    }
+
+    #[test]
+    fn replays_races_from_their_log() {
+        let script = r#"
+            function takeYourTurn()
+                GameState:buy_acceleration(math.random(0, 3))
+                if math.random() < 0.2 then GameState:buy_banana() end
+                if math.random() < 0.2 then GameState:buy_shell(1) end
+            end
+        "#;
+        let mut game = game_with_scripts([script; 3]);
+        game.race().unwrap();
+
+        let replayed = Game::from_log(game.race_log()).unwrap();
+        assert_eq!(replayed.state, State::Done);
+        assert_eq!(replayed.winner, game.winner);
+        assert_eq!(replayed.log_json(), game.log_json());
+    }
+
+    #[test]
+    fn replays_report_the_first_divergence() {
+        let accelerate = "function takeYourTurn() GameState:buy_acceleration(2) end";
+        let mut game = game_with_scripts([accelerate; 3]);
+        game.race().unwrap();
+
+        let mut log = game.race_log();
+        log.turns[3].purchases[2].action = Action::Acceleration { car: 2, amount: 1 };
+        let error = Game::from_log(log).unwrap_err();
+        assert!(
+            matches!(&error, GameError::Divergence { turn: 4, message } if message.contains("purchases")),
+            "{}",
+            error
+        );
+
+        let mut log = game.race_log();
+        log.turns.pop();
+        let error = Game::from_log(log).unwrap_err();
+        assert!(matches!(error, GameError::Divergence { .. }), "{}", error);
+    }
+
+    #[test]
+    fn streams_the_log_as_it_is_played() {
+        #[derive(Clone, Default)]
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let accelerate = "function takeYourTurn() GameState:buy_acceleration(2) end";
+        let sink = Shared::default();
+        let mut game = game_with_scripts([accelerate; 3]).with_log_sink(sink.clone());
+        game.race().unwrap();
+
+        let streamed = sink.0.lock().unwrap().clone();
+        let lines: Vec<&str> = std::str::from_utf8(&streamed).unwrap().lines().collect();
+        assert_eq!(lines.len(), game.logs.len() + 2);
+        assert!(lines[0].starts_with(r#"{"header":"#));
+        assert!(lines[1].starts_with(r#"{"turn":"#));
+        assert!(lines[lines.len() - 1].starts_with(r#"{"result":"#));
+
+        let log = RaceLog::from_ndjson(&streamed[..]).unwrap();
+        assert_eq!(serde_json::to_value(log).unwrap(), game.log_json());
+    }
+
+    #[test]
+    fn standings() {
+        let mut game = game();
+        game.cars[0].y = 10;
+        game.cars[1].y = 1004;
+        game.cars[2].y = 1004;
+        game.winner = Some(2);
+        assert_eq!(game.standings(), vec![2, 1, 0]);
+
+        game.winner = None;
+        assert_eq!(game.standings(), vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn shells() {
+        let mut game = game();
+        game.cars[0].y = 10;
+        game.cars[1].y = 30;
+        game.cars[1].speed = 5;
+        game.cars[2].y = 50;
+        game.cars[2].speed = 5;
+        game.bananas = vec![5, 20];
+        game.log_turn();
+
+        // the first shell hits the banana in the way, the second the closest car in front
+        game.apply_shell(2, 0);
+        assert_eq!(game.bananas, vec![5]);
+        assert_eq!(game.cars[1].speed, 0);
+        assert_eq!(game.cars[2].speed, 5);
+
+        // with no car in front, shells only take out bananas ahead
+        game.bananas = vec![5, 60];
+        game.apply_shell(2, 2);
+        assert_eq!(game.bananas, vec![5]);
+
+        assert_eq!(
+            game.logs[0].events,
+            vec![
+                Event::ShellIntercepted {
+                    turn: 1,
+                    car: 0,
+                    banana: 20
+                },
+                Event::ShellHit {
+                    turn: 1,
+                    car: 0,
+                    target: 1
+                },
+                Event::ShellIntercepted {
+                    turn: 1,
+                    car: 2,
+                    banana: 60
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn collisions_are_logged() {
+        let mut game = game();
+        game.cars[1].speed = 3;
+        game.bananas = vec![2];
+        game.log_turn();
+        game.resolve_turn(&[vec![0; 3], vec![0; 3], vec![0; 3]]);
+
+        assert_eq!((game.cars[1].y, game.cars[1].speed), (2, 0));
+        assert_eq!(
+            game.logs[0].events,
+            vec![Event::Collision {
+                turn: 1,
+                car: 1,
+                banana: 2
+            }]
+        );
     }
 }
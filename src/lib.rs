@@ -0,0 +1,65 @@
+//! Races between cars programmed in Lua, or in any language as an executable, that spend their
+//! balance on acceleration, bananas and shells priced by a VRGDA.
+//!
+//! A race is a [`Game`]: register [`Car`]s, each driven by a [`Runner`], start it and run it to
+//! the end, then read the winner and the [`RaceLog`].
+//!
+//! ```
+//! use portimao::runtime::LuaRunner;
+//! use portimao::{Car, Game, RaceConfig};
+//!
+//! let script = "function takeYourTurn() GameState:buy_acceleration(1) end";
+//! let mut game = Game::with_seed(RaceConfig::default(), 42);
+//! for name in ["Alice", "Bob"] {
+//!     let runner = LuaRunner::new(script.to_string());
+//!     game.register(Car::new(Box::new(runner), name.to_string()))?;
+//! }
+//! game.start()?;
+//! game.race()?;
+//!
+//! assert!(game.winner().is_some());
+//! # Ok::<(), portimao::GameError>(())
+//! ```
+
+#![warn(missing_docs)]
+
+/// What cars buy.
+pub mod action;
+/// The cars of a race.
+pub mod car;
+/// The rules of a race.
+pub mod config;
+/// Errors of the engine.
+pub mod error;
+/// The race itself.
+pub mod game;
+/// The record of a race.
+pub mod log;
+/// How items are priced.
+pub mod pricing;
+/// Ratings of cars over many races.
+pub mod rating;
+/// Race reports as HTML pages.
+pub mod report;
+/// The runtimes car programs run in.
+pub mod runtime;
+/// Many races run in parallel.
+pub mod simulate;
+/// Watching races in the terminal.
+pub mod spectator;
+/// Many races between a pool of cars.
+pub mod tournament;
+/// Numbers with 18 decimals, that prices are computed with.
+pub mod wad;
+
+pub use action::Action;
+pub use car::Car;
+pub use config::{ItemPricing, RaceConfig};
+pub use error::GameError;
+pub use game::{Game, Quote, State};
+pub use log::{Event, Header, Log, Outcome, Purchase, RaceLog, Record, LOG_VERSION};
+pub use pricing::PricingModel;
+pub use rating::{Ladder, Rating};
+pub use runtime::Runner;
+pub use simulate::{simulate_many, Simulations};
+pub use tournament::{Tournament, TournamentReport};
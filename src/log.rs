@@ -1,45 +1,187 @@
-use mlua::prelude::LuaError;
-use mlua::{IntoLua, Lua, Value};
+use std::fmt::{self, Debug};
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
+
 use serde::{Deserialize, Serialize};
 
 use crate::action::Action;
 use crate::car::Car;
+use crate::config::RaceConfig;
+use crate::runtime::{Budget, Penalty};
+
+/// Version of the log format, bumped whenever a log's shape changes.
+pub const LOG_VERSION: u32 = 2;
+
+/// A race's whole log, as [`Game::export_log`](crate::Game::export_log) writes it: a header,
+/// one record per turn played, then how the race ended.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RaceLog {
+    /// What the race was set up with.
+    pub header: Header,
+    /// One record per turn, in order.
+    pub turns: Vec<Log>,
+    /// The race as it ended.
+    pub result: Outcome,
+}
+
+impl RaceLog {
+    /// Reads a log streamed as NDJSON by [`Game::with_log_sink`](crate::Game::with_log_sink).
+    pub fn from_ndjson(reader: impl BufRead) -> io::Result<Self> {
+        let (mut header, mut turns, mut result) = (None, Vec::new(), None);
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line)? {
+                Record::Header(read) => header = Some(read),
+                Record::Turn(turn) => turns.push(turn),
+                Record::Result(read) => result = Some(read),
+            }
+        }
+
+        let missing = |part| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the log has no {}", part),
+            )
+        };
+        Ok(Self {
+            header: header.ok_or_else(|| missing("header"))?,
+            turns,
+            result: result.ok_or_else(|| missing("result"))?,
+        })
+    }
+}
+
+/// A line of a log streamed as NDJSON: the header as the race starts, a record after each turn,
+/// then the result once the race is done.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(missing_docs)]
+pub enum Record {
+    Header(Header),
+    Turn(Log),
+    Result(Outcome),
+}
+
+/// Where a race's log is streamed to, shared by the race's copies.
+#[derive(Clone)]
+pub(crate) struct LogSink(Arc<Mutex<dyn Write + Send>>);
+
+impl LogSink {
+    pub(crate) fn new(sink: impl Write + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(sink)))
+    }
+
+    /// Writes `record` as a line and flushes it, so that readers get it right away.
+    pub(crate) fn write(&self, record: &Record) -> io::Result<()> {
+        let mut sink = self.0.lock().unwrap();
+        serde_json::to_writer(&mut *sink, record)?;
+        sink.write_all(b"\n")?;
+        sink.flush()
+    }
+}
+
+impl Debug for LogSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LogSink")
+    }
+}
+
+/// What a race was set up with, enough to race the same cars again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Header {
+    /// The log format's [`LOG_VERSION`].
+    pub version: u32,
+    /// The rules of the race.
+    pub config: RaceConfig,
+    /// The race's seed.
+    pub seed: u64,
+    /// The cars' names, in seat order.
+    pub cars: Vec<String>,
+}
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+/// The record of a turn: the race as the turn started, then what the cars bought and what
+/// happened as the turn was played.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Log {
+    /// The turn, starting at 1.
+    pub turn: usize,
+    /// What each car bought, in seat order then in the order the car bought them.
+    pub purchases: Vec<Purchase>,
+    /// Actions applied during the turn, in order.
     pub actions: Vec<Action>,
+    /// Positions of the bananas on the track.
     pub bananas: Vec<u32>,
-    pub costs: Vec<u128>,
-    pub current_car: usize,
+    /// Prices of one acceleration, one banana and one shell, None (`null`) for the ones that are
+    /// unaffordable whatever the balance.
+    pub costs: Vec<Option<u128>>,
+    /// The cars, without their programs.
     pub cars: Vec<Car>,
+    /// Accelerations, bananas and shells sold so far.
     pub actions_sold: Vec<u128>,
+    /// What happened to cars during the turn besides their actions.
+    pub events: Vec<Event>,
 }
 
-impl Log {
-    pub fn add_info(
-        &mut self,
-        bananas: Vec<u32>,
-        costs: Vec<u128>,
-        current_car: usize,
-        cars: Vec<Car>,
-        actions_sold: Vec<u128>,
-    ) {
-        self.bananas = bananas;
-        self.costs = costs;
-        self.current_car = current_car;
-        self.cars = cars;
-        self.actions_sold = actions_sold;
-    }
+/// Items a car bought in one go, and what it paid for them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Purchase {
+    /// The items, as they are applied.
+    pub action: Action,
+    /// What the car paid.
+    pub cost: u128,
 }
 
-impl<'lua> IntoLua<'lua> for Log {
-    fn into_lua(self, lua: &'lua Lua) -> color_eyre::Result<Value<'lua>, LuaError> {
-        let table = lua.create_table()?;
-        table.set("actions", self.actions)?;
-        table.set("bananas", self.bananas)?;
-        table.set("costs", self.costs)?;
-        table.set("car", self.cars)?;
-        table.set("actions_sold", self.actions_sold)?;
-        Ok(Value::Table(table))
-    }
+/// The race as it ended.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Outcome {
+    /// The turn that would have come next.
+    pub turn: usize,
+    /// The cars, without their programs.
+    pub cars: Vec<Car>,
+    /// Positions of the bananas left on the track.
+    pub bananas: Vec<u32>,
+    /// Accelerations, bananas and shells sold.
+    pub actions_sold: Vec<u128>,
+    /// The index of the car that won, if any.
+    pub winner: Option<usize>,
+}
+
+/// Something that happened to a car during a turn, besides its actions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(missing_docs)]
+pub enum Event {
+    /// The car went over one of its budgets and got `penalty`.
+    BudgetExceeded {
+        turn: usize,
+        car: usize,
+        budget: Budget,
+        penalty: Penalty,
+    },
+    /// The car's program failed to play its turn, which it lost.
+    ScriptError {
+        turn: usize,
+        car: usize,
+        message: String,
+    },
+    /// The car ran into the banana at `banana`, and stopped there.
+    Collision {
+        turn: usize,
+        car: usize,
+        banana: u32,
+    },
+    /// A shell fired by `car` hit `target`, which stopped.
+    ShellHit {
+        turn: usize,
+        car: usize,
+        target: usize,
+    },
+    /// A shell fired by `car` hit the banana at `banana` instead of a car.
+    ShellIntercepted {
+        turn: usize,
+        car: usize,
+        banana: u32,
+    },
 }
@@ -1,22 +1,478 @@
 //#![warn(clippy::all, clippy::pedantic, clippy::cargo, clippy::nursery)]
 
-use crate::car::Car;
-use crate::game::Game;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
 
-mod action;
-mod car;
-mod game;
-mod log;
-mod runtime;
+use clap::{Args, Parser, Subcommand};
+use color_eyre::eyre::{bail, eyre, WrapErr};
+use color_eyre::Result;
 
-fn main() {
-    let mut game = Game::new();
+use portimao::runtime::{LuaLimits, LuaRunner, Penalty, ProcessRunner, WasmLimits, WasmRunner};
+use portimao::spectator::{self, Feed};
+use portimao::{Car, Game, Ladder, RaceConfig, RaceLog, Record, Runner, Tournament, LOG_VERSION};
 
-    let base_car = std::fs::read_to_string("scripts/base.lua").unwrap();
+#[derive(Debug, Parser)]
+#[command(
+    name = "portimao",
+    version,
+    about = "Race Lua-programmed cars against each other"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Race the given cars and export the race log
+    Race {
+        #[command(flatten)]
+        cars: CarArgs,
+        /// Seed of the race: the same cars and seed always produce the same race
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Where to write the race log (defaults to `logs/logs_<timestamp>.json`)
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+        /// Don't write the race log to a file, for races only followed through `--stream`
+        #[arg(long, conflicts_with = "out")]
+        no_out: bool,
+        /// Stream the race log as NDJSON, one line per turn as it is played, to FILE or to stdout
+        /// with `-`, which then only carries the log (Lua's `print` writes to stderr)
+        #[arg(long, value_name = "FILE")]
+        stream: Option<PathBuf>,
+        /// Watch the race in the terminal as it is played
+        #[arg(long, conflicts_with = "stream")]
+        watch: bool,
+        /// Wall-clock time every car has to decide each turn, in milliseconds, after which it is
+        /// disqualified (makes the race depend on the machine)
+        #[arg(long, value_name = "MS")]
+        time_budget: Option<u64>,
+    },
+    /// Race the given cars against each other in every seating and print how each did
+    Tournament {
+        #[command(flatten)]
+        cars: CarArgs,
+        /// How many cars race at once (defaults to as many as a race takes)
+        #[arg(long)]
+        seats: Option<usize>,
+        /// How many seeds, from 0, every seating is raced with
+        #[arg(long, default_value_t = 1)]
+        seeds: u64,
+        /// Where to write the report as JSON
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+    /// Check that a car script loads and defines `takeYourTurn`, that a WASM car instantiates and
+    /// exports `take_your_turn`, or that a car executable starts
+    Validate {
+        /// Script or executable to check
+        script: PathBuf,
+    },
+    /// Watch a race log in the terminal, with pause, step and seek controls
+    Watch {
+        /// Log file written by `race`, whole or streamed
+        log: PathBuf,
+    },
+    /// Write an HTML page charting a race log
+    Report {
+        /// Log file written by `race`, whole or streamed
+        log: PathBuf,
+        /// Where to write the page (defaults to the log's path with an `.html` extension)
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+    /// Update a rating ladder with the results of exported race logs and print it
+    Rate {
+        /// Ladder file, created if it doesn't exist
+        #[arg(long, value_name = "FILE", default_value = "ladder.json")]
+        ladder: PathBuf,
+        /// Log files written by `race`, rated in order
+        logs: Vec<PathBuf>,
+    },
+    /// Replay an exported race log, checking that its actions lead to the states it recorded,
+    /// and print a turn by turn summary
+    Replay {
+        /// Log file written by `race`
+        log: PathBuf,
+    },
+}
+
+/// The cars to race and the rules they race under.
+#[derive(Debug, Args)]
+struct CarArgs {
+    /// A car to register, as `name=path/to/script.lua`, `name=path/to/module.wasm` or
+    /// `name=path/to/executable` (repeat once per car, in seat order)
+    #[arg(long = "car", value_name = "NAME=PATH", required = true, value_parser = parse_car)]
+    cars: Vec<(String, PathBuf)>,
+    /// Race config file, TOML or JSON (defaults to the standard race)
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// How long executable cars have to answer each turn, in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 1000)]
+    turn_timeout: u64,
+    /// How many instructions a Lua car may run per turn
+    #[arg(long, value_name = "COUNT", default_value_t = LuaLimits::default().instructions)]
+    lua_instructions: u64,
+    /// How many bytes of memory a Lua car may allocate
+    #[arg(long, value_name = "BYTES", default_value_t = LuaLimits::default().memory)]
+    lua_memory: usize,
+    /// How much fuel a WASM car may consume per turn
+    #[arg(long, value_name = "FUEL", default_value_t = WasmLimits::default().fuel)]
+    wasm_fuel: u64,
+    /// How many bytes of linear memory a WASM car may have
+    #[arg(long, value_name = "BYTES", default_value_t = WasmLimits::default().memory)]
+    wasm_memory: usize,
+    /// What happens to a Lua or WASM car that goes over its budgets: `skip-turn` or
+    /// `disqualify`
+    #[arg(long, value_name = "PENALTY", default_value = "skip-turn", value_parser = parse_penalty)]
+    on_budget_exceeded: Penalty,
+}
+
+fn parse_car(arg: &str) -> Result<(String, PathBuf), String> {
+    match arg.split_once('=') {
+        Some((name, path)) if !name.is_empty() && !path.is_empty() => {
+            Ok((name.to_string(), PathBuf::from(path)))
+        }
+        _ => Err(format!("expected NAME=PATH, got `{}`", arg)),
+    }
+}
+
+fn parse_penalty(arg: &str) -> Result<Penalty, String> {
+    match arg {
+        "skip-turn" => Ok(Penalty::SkipTurn),
+        "disqualify" => Ok(Penalty::Disqualify),
+        _ => Err(format!("expected skip-turn or disqualify, got `{}`", arg)),
+    }
+}
+
+fn read_script(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path).wrap_err_with(|| format!("Failed to read {}", path.display()))
+}
+
+/// The budgets cars run under.
+#[derive(Debug, Clone, Copy, Default)]
+struct Limits {
+    turn_timeout: Duration,
+    lua: LuaLimits,
+    wasm: WasmLimits,
+}
+
+/// Lua scripts and WASM modules are recognized by their extension, anything else is run as an
+/// executable.
+fn runner(path: &Path, limits: Limits) -> Result<Box<dyn Runner>> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("lua") => Ok(Box::new(
+            LuaRunner::new(read_script(path)?)
+                .with_name(path.display().to_string())
+                .with_limits(limits.lua),
+        )),
+        Some("wasm") => {
+            let wasm = std::fs::read(path)
+                .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+            let runner = WasmRunner::new(&wasm).map_err(|e| eyre!("{}: {}", path.display(), e))?;
+            Ok(Box::new(runner.with_limits(limits.wasm)))
+        }
+        _ => Ok(Box::new(
+            ProcessRunner::new(path.to_path_buf(), Vec::new()).with_timeout(limits.turn_timeout),
+        )),
+    }
+}
+
+impl CarArgs {
+    fn config(&self) -> Result<RaceConfig> {
+        match &self.config {
+            Some(path) => RaceConfig::load(path).map_err(|e| eyre!("{}: {}", path.display(), e)),
+            None => Ok(RaceConfig::default()),
+        }
+    }
+
+    fn limits(&self) -> Limits {
+        Limits {
+            turn_timeout: Duration::from_millis(self.turn_timeout),
+            lua: LuaLimits {
+                instructions: self.lua_instructions,
+                memory: self.lua_memory,
+                penalty: self.on_budget_exceeded,
+            },
+            wasm: WasmLimits {
+                fuel: self.wasm_fuel,
+                memory: self.wasm_memory,
+                penalty: self.on_budget_exceeded,
+            },
+        }
+    }
+
+    /// Loads and validates every car.
+    fn cars(&self) -> Result<Vec<Car>> {
+        let limits = self.limits();
+        self.cars
+            .iter()
+            .map(|(name, path)| {
+                let runner = runner(path, limits)?;
+                runner
+                    .validate()
+                    .map_err(|e| eyre!("{} ({}): {}", name, path.display(), e))?;
+                Ok(Car::new(runner, name.clone()))
+            })
+            .collect()
+    }
+}
+
+/// Where the race log goes: the JSON file, unless there is none, and the NDJSON stream.
+#[derive(Debug)]
+struct LogOutput {
+    out: Option<PathBuf>,
+    no_out: bool,
+    stream: Option<PathBuf>,
+    watch: bool,
+}
+
+fn race(
+    cars: CarArgs,
+    seed: Option<u64>,
+    output: LogOutput,
+    time_budget: Option<u64>,
+) -> Result<()> {
+    let config = cars.config()?;
+    let mut game = match seed {
+        Some(seed) => Game::with_seed(config, seed),
+        None => Game::new(config),
+    };
+    if let Some(time_budget) = time_budget {
+        game = game.with_time_budget(Duration::from_millis(time_budget));
+    }
+    match output.stream {
+        Some(path) if path == Path::new("-") => game = game.with_log_sink(io::stdout()),
+        Some(path) => {
+            let file = File::create(&path)
+                .wrap_err_with(|| format!("Failed to create {}", path.display()))?;
+            game = game.with_log_sink(BufWriter::new(file));
+        }
+        None => {}
+    }
+    let mut records = None;
+    if output.watch {
+        let (sender, receiver) = mpsc::channel();
+        game = game.with_log_sink(Feed::new(sender));
+        records = Some(receiver);
+    }
+
+    for car in cars.cars()? {
+        game.register(car)?;
+    }
+
+    game.start()?;
+    let game = match records {
+        Some(records) => {
+            // the race is played while it is watched, and goes on if the spectator quits
+            let race = thread::spawn(move || game.race().map(|()| game));
+            spectator::watch(records).wrap_err("Failed to show the race")?;
+            race.join().expect("the race doesn't panic")?
+        }
+        None => {
+            game.race()?;
+            game
+        }
+    };
+
+    let winner = match game.winner() {
+        Some(car) => format!("{} won", car.name),
+        None => "No winner".to_string(),
+    };
+    if output.no_out {
+        eprintln!("{}", winner);
+    } else {
+        let path = game
+            .export_log(output.out.as_deref())
+            .wrap_err("Failed to export the race log")?;
+        eprintln!("{}, log written to {}", winner, path.display());
+    }
+
+    Ok(())
+}
+
+fn tournament(cars: CarArgs, seats: Option<usize>, seeds: u64, out: Option<PathBuf>) -> Result<()> {
+    let mut tournament =
+        Tournament::new(cars.config()?, cars.cars()?).with_seeds((0..seeds).collect());
+    if let Some(seats) = seats {
+        tournament = tournament.with_seats(seats);
+    }
+
+    let report = tournament.run()?;
+    print!("{}", report);
+
+    if let Some(out) = out {
+        let json = serde_json::to_string_pretty(&report)?;
+        std::fs::write(&out, json)
+            .wrap_err_with(|| format!("Failed to write {}", out.display()))?;
+    }
+
+    Ok(())
+}
+
+fn validate(script: &Path) -> Result<()> {
+    runner(script, Limits::default())?
+        .validate()
+        .map_err(|e| eyre!("{}: {}", script.display(), e))?;
+    eprintln!("{} is valid", script.display());
+
+    Ok(())
+}
+
+/// Reads a race log written by `race`, as JSON or streamed as NDJSON.
+fn read_log(log: &Path) -> Result<RaceLog> {
+    let text = std::fs::read_to_string(log)
+        .wrap_err_with(|| format!("Failed to read {}", log.display()))?;
+    let not_a_log = || format!("{} is not a race log", log.display());
+
+    // a whole log is one JSON value, a stream starts with its header line
+    let (json, ndjson) = match serde_json::from_str::<serde_json::Value>(&text) {
+        Ok(json) => (json, false),
+        Err(_) => {
+            let first = text.lines().next().unwrap_or_default();
+            let json: serde_json::Value = serde_json::from_str(first).wrap_err_with(not_a_log)?;
+            (json, true)
+        }
+    };
+
+    match json["header"]["version"].as_u64() {
+        Some(version) if version == LOG_VERSION as u64 => {}
+        Some(version) => bail!(
+            "{} is a version {} log, this portimao reads version {}",
+            log.display(),
+            version,
+            LOG_VERSION
+        ),
+        None => bail!(not_a_log()),
+    }
+
+    if ndjson {
+        RaceLog::from_ndjson(text.as_bytes()).wrap_err_with(not_a_log)
+    } else {
+        serde_json::from_value(json).wrap_err_with(not_a_log)
+    }
+}
+
+fn rate(ladder_path: &Path, logs: &[PathBuf]) -> Result<()> {
+    let mut ladder = Ladder::load(ladder_path)
+        .wrap_err_with(|| format!("Failed to read {}", ladder_path.display()))?;
+
+    for log in logs {
+        let result = read_log(log)?.result;
+        ladder.record_cars(&result.cars, result.winner);
+    }
+
+    ladder
+        .save(ladder_path)
+        .wrap_err_with(|| format!("Failed to write {}", ladder_path.display()))?;
+    print!("{}", ladder);
+
+    Ok(())
+}
+
+fn replay(log: &Path) -> Result<()> {
+    let game = Game::from_log(read_log(log)?)
+        .wrap_err_with(|| format!("{} does not replay", log.display()))?;
+
+    for entry in game.logs() {
+        let costs: Vec<String> = entry
+            .costs
+            .iter()
+            .map(|cost| cost.map_or("unaffordable".to_string(), |cost| cost.to_string()))
+            .collect();
+        println!(
+            "turn {}: bananas {:?}, costs [{}]",
+            entry.turn,
+            entry.bananas,
+            costs.join(", ")
+        );
+        for car in &entry.cars {
+            println!(
+                "  {:<12} y {:>5}  speed {:>4}  balance {:>6}",
+                car.name, car.y, car.speed, car.balance
+            );
+        }
+        for purchase in &entry.purchases {
+            println!("  bought {:?} for {}", purchase.action, purchase.cost);
+        }
+        for event in &entry.events {
+            println!("  {:?}", event);
+        }
+    }
+
+    match game.winner() {
+        Some(car) => println!("winner: {}", car.name),
+        None => println!("winner: none"),
+    }
+    println!(
+        "replayed {} turns, every state matches the log",
+        game.logs().len()
+    );
+
+    Ok(())
+}
+
+fn watch(log: &Path) -> Result<()> {
+    let log = read_log(log)?;
+    let (sender, records) = mpsc::channel();
+    let turns = log.turns.into_iter().map(Record::Turn);
+    for record in std::iter::once(Record::Header(log.header))
+        .chain(turns)
+        .chain([Record::Result(log.result)])
+    {
+        sender.send(record).expect("records are received below");
+    }
+
+    spectator::watch(records).wrap_err("Failed to show the race")
+}
+
+fn report(log: &Path, out: Option<PathBuf>) -> Result<()> {
+    let page = portimao::report::html(&read_log(log)?);
+    let out = out.unwrap_or_else(|| log.with_extension("html"));
+    std::fs::write(&out, page).wrap_err_with(|| format!("Failed to write {}", out.display()))?;
+    eprintln!("report written to {}", out.display());
+
+    Ok(())
+}
 
-    game.register(Car::new(base_car.clone(), "Alice".to_string()));
-    game.register(Car::new(base_car.clone(), "Bob".to_string()));
-    game.register(Car::new(base_car.clone(), "Charlie".to_string()));
+fn main() -> Result<()> {
+    color_eyre::install()?;
 
-    game.race();
+    match Cli::parse().command {
+        Command::Race {
+            cars,
+            seed,
+            out,
+            no_out,
+            stream,
+            watch,
+            time_budget,
+        } => race(
+            cars,
+            seed,
+            LogOutput {
+                out,
+                no_out,
+                stream,
+                watch,
+            },
+            time_budget,
+        ),
+        Command::Tournament {
+            cars,
+            seats,
+            seeds,
+            out,
+        } => tournament(cars, seats, seeds, out),
+        Command::Validate { script } => validate(&script),
+        Command::Rate { ladder, logs } => rate(&ladder, &logs),
+        Command::Replay { log } => replay(&log),
+        Command::Watch { log } => watch(&log),
+        Command::Report { log, out } => report(&log, out),
+    }
 }
@@ -0,0 +1,587 @@
+use serde::{Deserialize, Serialize};
+
+use crate::wad::{self, WAD};
+
+/// How an item's price moves with the turns and the items sold.
+///
+/// Prices are variable rate gradual Dutch auctions (VRGDA) or fixed: a VRGDA targets selling
+/// items on a schedule, and its price drops while sales are behind the schedule and rises while
+/// they are ahead of it.
+///
+/// Prices are computed with [18 decimals](crate::wad), from parameters rounded to 18 decimals, and
+/// every step rounds toward zero. They come out as the exact price rounded down, give or take
+/// `1e-14` of it when `per_turn_decrease` is at least 1%.
+///
+/// An item is unaffordable, whatever the balance, when it can't be sold anymore or its price is
+/// too high to count.
+pub trait PricingModel {
+    /// Price of the next item on turn `turn`, starting at 1, when `sold` items have sold so far,
+    /// or None when it's unaffordable. Prices must not drop as more items sell.
+    fn price(&self, turn: u64, sold: u128) -> Option<u128>;
+
+    /// What `amount` items cost together, each priced as if bought one after the other, or None
+    /// when they cost more than `budget` or one of them is unaffordable.
+    ///
+    /// Pricing counts the items of the same price at once and stops at the first item over
+    /// budget, so that no `amount` takes long to price: items that go through more than
+    /// [`MAX_PRICES`] different prices are unaffordable. The same goes for
+    /// [`affordable`](Self::affordable).
+    fn cost(&self, turn: u64, sold: u128, amount: u32, budget: u128) -> Option<u128> {
+        let price = |i: u32| self.price(turn, sold.checked_add(i as u128)?);
+        let (bought, total) = buy(price, amount, budget);
+        (bought == amount).then_some(total)
+    }
+
+    /// The most items `budget` buys, one after the other, up to `u32::MAX`.
+    fn affordable(&self, turn: u64, sold: u128, budget: u128) -> u32 {
+        let price = |i: u32| self.price(turn, sold.checked_add(i as u128)?);
+        buy(price, u32::MAX, budget).0
+    }
+
+    /// Checks that the model's parameters make sense, naming the first one that doesn't.
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// The most different prices a purchase goes through before its items count as unaffordable.
+pub const MAX_PRICES: usize = 1_000;
+
+/// How many of the first `amount` items `budget` buys, one after the other at the prices `price`
+/// gives them, and what they cost together. Pricing stops at the first unaffordable item or
+/// after [`MAX_PRICES`] different prices.
+fn buy(price: impl Fn(u32) -> Option<u128>, amount: u32, budget: u128) -> (u32, u128) {
+    let (mut bought, mut total) = (0, 0);
+    for _ in 0..MAX_PRICES {
+        if bought == amount {
+            break;
+        }
+        let Some(unit) = price(bought) else {
+            break;
+        };
+        let count = (same_price_until(&price, bought, unit, amount) - bought) as u128;
+        let paid = match unit {
+            0 => count,
+            unit => count.min((budget - total) / unit),
+        };
+        bought += paid as u32;
+        total += paid * unit;
+        if paid < count {
+            break;
+        }
+    }
+    (bought, total)
+}
+
+/// The first item after `first`, up to `amount`, that `price` doesn't price at `unit` like
+/// `first`. Prices don't drop as items sell, so it's found by doubling the step from `first`
+/// until the price changes, then halving it.
+fn same_price_until(
+    price: &impl Fn(u32) -> Option<u128>,
+    first: u32,
+    unit: u128,
+    amount: u32,
+) -> u32 {
+    let (mut same, mut step) = (first, 1);
+    let mut other = loop {
+        let next = same.saturating_add(step).min(amount);
+        if next == amount || price(next) != Some(unit) {
+            break next;
+        }
+        same = next;
+        step = step.saturating_mul(2);
+    };
+    while other - same > 1 {
+        let middle = same + (other - same) / 2;
+        if price(middle) == Some(unit) {
+            same = middle;
+        } else {
+            other = middle;
+        }
+    }
+    other
+}
+
+/// A VRGDA targeting `sell_per_turn` items per turn, whose price is multiplied by
+/// `1 - per_turn_decrease` for every turn sales are behind.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExponentialVrgda {
+    /// Price of the item when it sells exactly `sell_per_turn` items per turn.
+    pub target_price: u64,
+    /// Fraction the price drops by for every turn nothing is sold.
+    pub per_turn_decrease: f64,
+    /// Number of items the price targets selling per turn.
+    pub sell_per_turn: f64,
+}
+
+/// A VRGDA targeting `sell_per_turn` items per turn, whose price drops by `per_turn_decrease`
+/// times `target_price` for every turn sales are behind, down to nothing, and rises as much for
+/// every turn they are ahead.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LinearVrgda {
+    /// Price of the item when it sells exactly `sell_per_turn` items per turn.
+    pub target_price: u64,
+    /// Fraction of `target_price` the price drops by for every turn nothing is sold.
+    pub per_turn_decrease: f64,
+    /// Number of items the price targets selling per turn.
+    pub sell_per_turn: f64,
+}
+
+/// A VRGDA targeting sales that start fast and level off at `max_sellable` items, along a
+/// logistic curve as steep as `time_scale`. Its price is multiplied by `1 - per_turn_decrease`
+/// for every turn sales are behind, and no more than `max_sellable` items can be sold.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogisticVrgda {
+    /// Price of the item when sales are on the curve.
+    pub target_price: u64,
+    /// Fraction the price drops by for every turn nothing is sold.
+    pub per_turn_decrease: f64,
+    /// Number of items that can ever be sold.
+    pub max_sellable: f64,
+    /// How fast the targeted sales reach `max_sellable`.
+    pub time_scale: f64,
+}
+
+/// The same price whatever the turn and the items sold.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FixedPrice {
+    /// Price of every item.
+    pub price: u64,
+}
+
+/// How many turns the `sold + 1`th item sells behind a schedule selling `sell_per_turn` items
+/// per turn from turn 1, negative when it sells ahead, or None when it's too far ahead to count.
+fn turns_behind(turn: u64, sold: u128, sell_per_turn: f64) -> Option<i128> {
+    let schedule = wad::div(
+        wad::from_int(sold.checked_add(1)?)?,
+        wad::from_f64(sell_per_turn)?,
+    )?;
+    (wad::from_int(turn as u128)? - WAD).checked_sub(schedule)
+}
+
+/// `target_price * (1 - per_turn_decrease) ^ behind`, or None when it doesn't fit.
+fn decayed(target_price: u64, per_turn_decrease: f64, behind: i128) -> Option<u128> {
+    let decay = wad::ln(WAD - wad::from_f64(per_turn_decrease)?)?;
+    let Some(exponent) = wad::mul(decay, behind) else {
+        // so far behind that the price rounds to nothing
+        return (behind > 0).then_some(0);
+    };
+    // a whole number times a WAD is a whole number
+    let price = wad::mul(target_price as i128, wad::exp(exponent)?)?;
+    Some(price as u128)
+}
+
+fn check_decrease(per_turn_decrease: f64) -> Result<(), String> {
+    if per_turn_decrease > 0.0 && per_turn_decrease < 1.0 {
+        Ok(())
+    } else {
+        Err("per_turn_decrease must be between 0 and 1".to_string())
+    }
+}
+
+fn check_positive(name: &str, value: f64) -> Result<(), String> {
+    if value > 0.0 {
+        Ok(())
+    } else {
+        Err(format!("{} must be positive", name))
+    }
+}
+
+impl PricingModel for ExponentialVrgda {
+    fn price(&self, turn: u64, sold: u128) -> Option<u128> {
+        let behind = turns_behind(turn, sold, self.sell_per_turn)?;
+        decayed(self.target_price, self.per_turn_decrease, behind)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        check_decrease(self.per_turn_decrease)?;
+        check_positive("sell_per_turn", self.sell_per_turn)
+    }
+}
+
+impl PricingModel for LinearVrgda {
+    fn price(&self, turn: u64, sold: u128) -> Option<u128> {
+        let behind = turns_behind(turn, sold, self.sell_per_turn)?;
+        let Some(drop) = wad::mul(wad::from_f64(self.per_turn_decrease)?, behind) else {
+            return (behind > 0).then_some(0);
+        };
+        let price = wad::mul(self.target_price as i128, WAD.checked_sub(drop)?)?;
+        Some(price.max(0) as u128)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        check_decrease(self.per_turn_decrease)?;
+        check_positive("sell_per_turn", self.sell_per_turn)
+    }
+}
+
+impl LogisticVrgda {
+    /// How many turns the `sold + 1`th item sells behind the curve, or None when it's too far
+    /// ahead to count or can't be sold.
+    fn turns_behind(&self, turn: u64, sold: u128) -> Option<i128> {
+        let limit = wad::from_f64(self.max_sellable)?.checked_add(WAD)?;
+        let next = wad::from_int(sold.checked_add(1)?)?;
+        if next >= limit {
+            return None;
+        }
+
+        // the turn the curve reaches `next` items sold, counting from turn 1
+        let fraction = wad::div(limit - next, next.checked_add(limit)?)?;
+        let target_turn = -wad::div(wad::ln(fraction)?, wad::from_f64(self.time_scale)?)?;
+        (wad::from_int(turn as u128)? - WAD).checked_sub(target_turn)
+    }
+}
+
+impl PricingModel for LogisticVrgda {
+    fn price(&self, turn: u64, sold: u128) -> Option<u128> {
+        let behind = self.turns_behind(turn, sold)?;
+        decayed(self.target_price, self.per_turn_decrease, behind)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        check_decrease(self.per_turn_decrease)?;
+        check_positive("max_sellable", self.max_sellable)?;
+        check_positive("time_scale", self.time_scale)
+    }
+}
+
+impl PricingModel for FixedPrice {
+    fn price(&self, _turn: u64, _sold: u128) -> Option<u128> {
+        Some(self.price as u128)
+    }
+
+    fn cost(&self, _turn: u64, sold: u128, amount: u32, budget: u128) -> Option<u128> {
+        // the items sold still count once they're bought
+        sold.checked_add(amount.saturating_sub(1) as u128)?;
+        let total = (self.price as u128).checked_mul(amount as u128)?;
+        (total <= budget).then_some(total)
+    }
+
+    fn affordable(&self, _turn: u64, sold: u128, budget: u128) -> u32 {
+        let sellable = (u128::MAX - sold).saturating_add(1);
+        let most = match self.price {
+            0 => sellable,
+            price => sellable.min(budget / price as u128),
+        };
+        most.min(u32::MAX as u128) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use dashu_float::DBig;
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::config::ItemPricing;
+
+    const EXPONENTIAL: ExponentialVrgda = ExponentialVrgda {
+        target_price: 100,
+        per_turn_decrease: 0.2,
+        sell_per_turn: 2.0,
+    };
+
+    #[test]
+    fn vrgdas_follow_their_schedule() {
+        let linear = LinearVrgda {
+            target_price: 100,
+            per_turn_decrease: 0.2,
+            sell_per_turn: 2.0,
+        };
+        // the 4th item is on schedule on turn 3
+        assert_eq!(EXPONENTIAL.price(3, 3), Some(100));
+        assert_eq!(linear.price(3, 3), Some(100));
+
+        // a turn behind
+        assert_eq!(EXPONENTIAL.price(4, 3), Some(80));
+        assert_eq!(linear.price(4, 3), Some(80));
+        // a turn ahead: ln 0.8 rounds toward zero, so 125 comes out a hair below and rounds down
+        assert_eq!(EXPONENTIAL.price(3, 5), Some(124));
+        assert_eq!(linear.price(3, 5), Some(120));
+        // far behind, a linear price runs out
+        assert_eq!(linear.price(100, 0), Some(0));
+
+        assert_eq!(EXPONENTIAL.cost(4, 3, 2, u128::MAX), Some(80 + 89));
+        assert_eq!(EXPONENTIAL.cost(4, 3, 2, 80 + 89), Some(80 + 89));
+        assert_eq!(EXPONENTIAL.cost(4, 3, 2, 80 + 88), None);
+    }
+
+    #[test]
+    fn logistic_vrgdas_sell_out() {
+        let logistic = LogisticVrgda {
+            target_price: 100,
+            per_turn_decrease: 0.2,
+            max_sellable: 10.0,
+            time_scale: 0.5,
+        };
+        assert!(logistic.price(1, 0) > logistic.price(5, 0));
+        assert!(logistic.price(5, 5) > logistic.price(5, 4));
+        assert!(logistic.price(1_000, 9).is_some());
+
+        assert_eq!(logistic.price(1_000, 10), None);
+        assert_eq!(logistic.price(1_000, u128::MAX), None);
+        assert_eq!(logistic.cost(1_000, 9, 3, u128::MAX), None);
+    }
+
+    /// `x` with 40 digits, enough for the exact prices to be exact well past their 18th decimal.
+    fn exact(x: impl ToString) -> DBig {
+        DBig::from_str(&x.to_string())
+            .unwrap()
+            .with_precision(40)
+            .value()
+    }
+
+    /// A parameter rounded to 18 decimals, as the prices use it.
+    fn parameter(x: f64) -> DBig {
+        exact(format!("{}e-18", wad::from_f64(x).unwrap()))
+    }
+
+    /// `target_price * (1 - per_turn_decrease) ^ behind`.
+    fn exact_decayed(target_price: u64, per_turn_decrease: f64, behind: DBig) -> DBig {
+        let decay = (exact(1) - parameter(per_turn_decrease)).ln();
+        exact(target_price) * (decay * behind).exp()
+    }
+
+    fn exact_behind(turn: u64, sold: u128, sell_per_turn: f64) -> DBig {
+        exact(turn - 1) - exact(sold + 1) / parameter(sell_per_turn)
+    }
+
+    #[test]
+    fn prices_match_a_high_precision_reference() {
+        let acceleration = ExponentialVrgda {
+            target_price: 10,
+            per_turn_decrease: 0.33,
+            sell_per_turn: 2.0,
+        };
+        let banana = ExponentialVrgda {
+            target_price: 200,
+            per_turn_decrease: 0.33,
+            sell_per_turn: 0.2,
+        };
+        let linear = LinearVrgda {
+            target_price: 1_000,
+            per_turn_decrease: 0.07,
+            sell_per_turn: 1.3,
+        };
+        let logistic = LogisticVrgda {
+            target_price: 500,
+            per_turn_decrease: 0.15,
+            max_sellable: 150.0,
+            time_scale: 0.1,
+        };
+
+        for turn in (1..=61).step_by(6) {
+            for sold in (0..=150).step_by(15) {
+                let mut prices = vec![];
+                for model in [&acceleration, &banana] {
+                    let behind = exact_behind(turn, sold, model.sell_per_turn);
+                    let price = exact_decayed(model.target_price, model.per_turn_decrease, behind);
+                    prices.push((model.price(turn, sold), Some(price)));
+                }
+
+                let behind = exact_behind(turn, sold, linear.sell_per_turn);
+                let drop = parameter(linear.per_turn_decrease) * behind;
+                let price = exact(linear.target_price) * (exact(1) - drop);
+                prices.push((linear.price(turn, sold), Some(price.max(exact(0)))));
+
+                let (limit, next) = (parameter(logistic.max_sellable) + exact(1), exact(sold + 1));
+                let price = (next < limit).then(|| {
+                    let target_turn =
+                        -((&limit - &next) / (next + limit)).ln() / parameter(logistic.time_scale);
+                    let behind = exact(turn - 1) - target_turn;
+                    exact_decayed(logistic.target_price, logistic.per_turn_decrease, behind)
+                });
+                prices.push((logistic.price(turn, sold), price));
+
+                for (price, expected) in prices {
+                    let context = format!("{:?} on turn {} after {} sold", price, turn, sold);
+                    let Some(expected) = expected else {
+                        assert_eq!(price, None, "{}", context);
+                        continue;
+                    };
+                    let Some(price) = price else {
+                        assert!(expected > exact(u64::MAX), "{}", context);
+                        continue;
+                    };
+
+                    // the exact price rounded down, give or take 1e-14 of it
+                    let tolerance = &expected * exact("1e-14");
+                    let error = &expected - exact(price);
+                    assert!(error >= -tolerance.clone(), "{}", context);
+                    assert!(error < exact(1) + tolerance, "{}", context);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn validate() {
+        assert!(EXPONENTIAL.validate().is_ok());
+        assert!(FixedPrice { price: 0 }.validate().is_ok());
+
+        let logistic = LogisticVrgda {
+            target_price: 100,
+            per_turn_decrease: 0.2,
+            max_sellable: 0.0,
+            time_scale: 0.5,
+        };
+        assert_eq!(
+            logistic.validate(),
+            Err("max_sellable must be positive".to_string())
+        );
+    }
+
+    #[test]
+    fn extreme_budgets() {
+        let budget = u64::MAX as u128;
+        assert_eq!(FixedPrice { price: 1 }.affordable(1, 0, budget), u32::MAX);
+        assert_eq!(FixedPrice { price: 0 }.affordable(1, u128::MAX, 0), 1);
+        assert_eq!(FixedPrice { price: 3 }.affordable(1, 0, 10), 3);
+
+        // prices that barely move, in long runs of the same price
+        let slow = LinearVrgda {
+            target_price: 1,
+            per_turn_decrease: 1e-9,
+            sell_per_turn: 1e9,
+        };
+        let most = slow.affordable(1, 0, budget);
+        assert!(most > 1_000_000);
+        assert!(slow.cost(1, 0, most, budget).is_some());
+
+        // prices that change with every item go through too many of them
+        let fast = ExponentialVrgda {
+            target_price: 1_000_000,
+            per_turn_decrease: 1e-6,
+            sell_per_turn: 1.0,
+        };
+        assert_eq!(fast.affordable(1, 0, budget), MAX_PRICES as u32);
+        assert_eq!(fast.cost(1, 0, MAX_PRICES as u32 + 1, budget), None);
+    }
+
+    /// Models with any parameters that pass validation, some of them extreme.
+    fn models() -> impl Strategy<Value = ItemPricing> {
+        let decrease = prop_oneof![
+            0.001..0.999,
+            Just(f64::MIN_POSITIVE),
+            Just(1.0 - f64::EPSILON)
+        ];
+        let positive = prop_oneof![1e-9..1e9, Just(f64::MIN_POSITIVE), Just(f64::MAX)];
+        prop_oneof![
+            (any::<u64>(), decrease.clone(), positive.clone()).prop_map(
+                |(price, decrease, rate)| {
+                    ItemPricing::Exponential(ExponentialVrgda {
+                        target_price: price,
+                        per_turn_decrease: decrease,
+                        sell_per_turn: rate,
+                    })
+                }
+            ),
+            (any::<u64>(), decrease.clone(), positive.clone()).prop_map(
+                |(price, decrease, rate)| {
+                    ItemPricing::Linear(LinearVrgda {
+                        target_price: price,
+                        per_turn_decrease: decrease,
+                        sell_per_turn: rate,
+                    })
+                }
+            ),
+            (any::<u64>(), decrease, positive.clone(), positive).prop_map(
+                |(price, decrease, max, scale)| {
+                    ItemPricing::Logistic(LogisticVrgda {
+                        target_price: price,
+                        per_turn_decrease: decrease,
+                        max_sellable: max,
+                        time_scale: scale,
+                    })
+                }
+            ),
+            any::<u64>().prop_map(|price| ItemPricing::Fixed(FixedPrice { price })),
+        ]
+    }
+
+    /// Mostly small numbers, the ones races play with, and some as large as they go.
+    fn turns() -> impl Strategy<Value = u64> {
+        prop_oneof![0..1_000u64, any::<u64>()]
+    }
+
+    fn sold() -> impl Strategy<Value = u128> {
+        prop_oneof![0..1_000u128, any::<u128>(), Just(u128::MAX)]
+    }
+
+    proptest! {
+        #[test]
+        fn prices_never_drop_as_items_sell(model in models(), turn in turns(), sold in sold()) {
+            let model = model.model();
+            prop_assert!(model.validate().is_ok());
+            match (model.price(turn, sold), model.price(turn, sold.saturating_add(1))) {
+                (Some(price), Some(next)) => prop_assert!(price <= next),
+                (None, next) => prop_assert_eq!(next, None),
+                (Some(_), None) => {}
+            }
+        }
+
+        #[test]
+        fn costs_are_checked_sums_within_budget(
+            model in models(),
+            turn in turns(),
+            sold in sold(),
+            amount in 0..50u32,
+            budget in prop_oneof![0..100_000u128, any::<u128>()],
+        ) {
+            let model = model.model();
+            let total = (0..amount)
+                .map(|i| model.price(turn, sold.checked_add(i as u128)?))
+                .try_fold(0, |total: u128, price| total.checked_add(price?))
+                .filter(|&total| total <= budget);
+            prop_assert_eq!(model.cost(turn, sold, amount, budget), total);
+        }
+
+        #[test]
+        fn affordable_is_the_most_the_budget_buys(
+            model in models(),
+            turn in turns(),
+            sold in sold(),
+            budget in 0..20_000u128,
+        ) {
+            let model = model.model();
+            let most = model.affordable(turn, sold, budget);
+            prop_assert!(model.cost(turn, sold, most, budget).is_some());
+            if most < u32::MAX {
+                prop_assert_eq!(model.cost(turn, sold, most + 1, budget), None);
+            }
+        }
+
+        #[test]
+        fn affordable_is_the_most_an_extreme_budget_buys(
+            model in models(),
+            turn in turns(),
+            sold in sold(),
+            budget in prop_oneof![any::<u128>(), Just(u64::MAX as u128), Just(u128::MAX)],
+        ) {
+            let model = model.model();
+            let most = model.affordable(turn, sold, budget);
+            prop_assert!(model.cost(turn, sold, most, budget).is_some());
+            if most < u32::MAX {
+                prop_assert_eq!(model.cost(turn, sold, most + 1, budget), None);
+            }
+        }
+
+        #[test]
+        fn any_amount_prices_within_a_race_budget(
+            model in models(),
+            turn in turns(),
+            sold in sold(),
+            amount in any::<u32>(),
+            budget in 0..20_000u128,
+        ) {
+            let cost = model.model().cost(turn, sold, amount, budget);
+            prop_assert!(cost.is_none_or(|cost| cost <= budget));
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::car::Car;
+use crate::game::Game;
+
+/// Skill everyone starts with.
+const MU: f64 = 25.0;
+/// Uncertainty everyone starts with.
+const SIGMA: f64 = MU / 3.0;
+/// Skill difference that gives about 76% chances of finishing ahead.
+const BETA: f64 = SIGMA / 2.0;
+/// Floor of the factor an uncertainty shrinks by in one race.
+const KAPPA: f64 = 0.0001;
+
+/// Ratings of cars by name, updated from race results.
+///
+/// Ratings follow the TrueSkill model, a skill `mu` with an uncertainty `sigma`, updated with
+/// Weng and Lin's Bradley-Terry approximation, which handles races of any number of cars as all
+/// the pairs of cars in the race. Name new versions of a car differently to rate them apart.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Ladder {
+    ratings: BTreeMap<String, Rating>,
+}
+
+/// A car's estimated skill.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rating {
+    /// Estimated skill.
+    pub mu: f64,
+    /// Uncertainty of the estimate.
+    pub sigma: f64,
+    /// Races rated.
+    pub races: usize,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Self {
+            mu: MU,
+            sigma: SIGMA,
+            races: 0,
+        }
+    }
+}
+
+impl Rating {
+    /// The skill the car almost surely has, `mu - 3 * sigma`, which ladders are sorted by.
+    pub fn conservative(&self) -> f64 {
+        self.mu - 3.0 * self.sigma
+    }
+}
+
+impl Ladder {
+    /// Reads a ladder written by [`Ladder::save`], or returns an empty ladder if `path` does not
+    /// exist.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).map_err(io::Error::from),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the ladder as JSON.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// The car's rating, the starting one if it never raced.
+    pub fn rating(&self, name: &str) -> Rating {
+        self.ratings.get(name).copied().unwrap_or_default()
+    }
+
+    /// The rated cars, best first.
+    pub fn standings(&self) -> Vec<(&str, &Rating)> {
+        let mut standings: Vec<(&str, &Rating)> = self
+            .ratings
+            .iter()
+            .map(|(name, rating)| (name.as_str(), rating))
+            .collect();
+        standings.sort_by(|(_, a), (_, b)| b.conservative().total_cmp(&a.conservative()));
+        standings
+    }
+
+    /// Rates a finished race.
+    pub fn record_race(&mut self, game: &Game) {
+        let winner = game.winner().map(|_| game.standings()[0]);
+        self.record_cars(game.cars(), winner);
+    }
+
+    /// Rates a race from its cars' final state and its winner, as found in a race log.
+    pub fn record_cars(&mut self, cars: &[Car], winner: Option<usize>) {
+        let names: Vec<&str> = cars.iter().map(|car| car.name.as_str()).collect();
+        self.record(&names, &ranks(cars, winner));
+    }
+
+    /// Rates a race where `names[i]` finished with rank `ranks[i]`, 0 being first. Cars with the
+    /// same rank tied.
+    pub fn record(&mut self, names: &[&str], ranks: &[usize]) {
+        let before: Vec<Rating> = names.iter().map(|name| self.rating(name)).collect();
+
+        for (i, (name, rating)) in names.iter().zip(&before).enumerate() {
+            let variance = rating.sigma.powi(2);
+            let (mut omega, mut delta) = (0.0, 0.0);
+
+            for (q, other) in before.iter().enumerate().filter(|&(q, _)| q != i) {
+                let c = (variance + other.sigma.powi(2) + 2.0 * BETA.powi(2)).sqrt();
+                // chances that `i` finishes ahead of `q`
+                let p = 1.0 / (1.0 + ((other.mu - rating.mu) / c).exp());
+                let score = match ranks[i].cmp(&ranks[q]) {
+                    Ordering::Less => 1.0,
+                    Ordering::Equal => 0.5,
+                    Ordering::Greater => 0.0,
+                };
+
+                omega += variance / c * (score - p);
+                delta += rating.sigma / c * variance / c.powi(2) * p * (1.0 - p);
+            }
+
+            self.ratings.insert(
+                name.to_string(),
+                Rating {
+                    mu: rating.mu + omega,
+                    sigma: rating.sigma * (1.0 - delta).max(KAPPA).sqrt(),
+                    races: rating.races + 1,
+                },
+            );
+        }
+    }
+}
+
+/// Each car's rank: the winner first, then the others by distance covered, cars that covered the
+/// same distance tying.
+pub fn ranks(cars: &[Car], winner: Option<usize>) -> Vec<usize> {
+    cars.iter()
+        .enumerate()
+        .map(|(index, car)| {
+            if winner == Some(index) {
+                return 0;
+            }
+            cars.iter()
+                .enumerate()
+                .filter(|&(other, other_car)| other_car.y > car.y || winner == Some(other))
+                .count()
+        })
+        .collect()
+}
+
+impl Display for Ladder {
+    /// A table of the cars, best first.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<16} {:>8} {:>8} {:>8} {:>6}",
+            "car", "rating", "mu", "sigma", "races"
+        )?;
+        for (name, rating) in self.standings() {
+            writeln!(
+                f,
+                "{:<16} {:>8.2} {:>8.2} {:>8.2} {:>6}",
+                name,
+                rating.conservative(),
+                rating.mu,
+                rating.sigma,
+                rating.races
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranks_put_the_winner_first() {
+        let cars: Vec<Car> = [1004, 10, 1004, 10]
+            .into_iter()
+            .map(|y| Car {
+                balance: 0,
+                speed: 0,
+                y,
+                runner: None,
+                name: String::new(),
+                disqualified: false,
+            })
+            .collect();
+
+        assert_eq!(ranks(&cars, Some(2)), vec![1, 2, 0, 2]);
+        assert_eq!(ranks(&cars, None), vec![0, 2, 0, 2]);
+    }
+
+    #[test]
+    fn winners_go_up() {
+        let mut ladder = Ladder::default();
+        ladder.record(&["a", "b", "c"], &[0, 1, 2]);
+
+        let [a, b, c] = ["a", "b", "c"].map(|name| ladder.rating(name));
+        assert!(a.mu > b.mu && b.mu > c.mu);
+        assert!((b.mu - MU).abs() < 1e-9);
+        assert!(a.sigma < SIGMA && a.races == 1);
+        assert_eq!(
+            ladder
+                .standings()
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>(),
+            ["a", "b", "c"]
+        );
+
+        // beating a stronger car is worth more than beating a new one
+        let mut upset = ladder.clone();
+        upset.record(&["c", "a"], &[0, 1]);
+        let mut expected = ladder.clone();
+        expected.record(&["c", "d"], &[0, 1]);
+        assert!(upset.rating("c").mu > expected.rating("c").mu);
+
+        // a tie between equals changes nothing but the uncertainty
+        ladder.record(&["d", "e"], &[0, 0]);
+        assert_eq!(ladder.rating("d").mu, MU);
+        assert!(ladder.rating("d").sigma < SIGMA);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path =
+            std::env::temp_dir().join(format!("portimao-ladder-{}.json", std::process::id()));
+        assert_eq!(Ladder::load(&path).unwrap(), Ladder::default());
+
+        let mut ladder = Ladder::default();
+        ladder.record(&["a", "b"], &[0, 1]);
+        ladder.save(&path).unwrap();
+        assert_eq!(Ladder::load(&path).unwrap(), ladder);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,389 @@
+use std::fmt::Write;
+
+use crate::action::Action;
+use crate::car::Car;
+use crate::log::{Event, RaceLog};
+
+/// Colors the cars are drawn in, by seat.
+const COLORS: [&str; 8] = [
+    "#1f77b4", "#d62728", "#2ca02c", "#9467bd", "#ff7f0e", "#17becf", "#8c564b", "#e377c2",
+];
+const WIDTH: f64 = 760.0;
+const HEIGHT: f64 = 300.0;
+/// Room left of, right of, above and below the plot for the axes and the legend.
+const MARGIN: (f64, f64, f64, f64) = (70.0, 130.0, 30.0, 40.0);
+
+/// A line of a chart.
+struct Series {
+    name: String,
+    color: &'static str,
+    points: Vec<(f64, f64)>,
+}
+
+/// Something that happened at a point of a chart.
+struct Marker {
+    x: f64,
+    y: f64,
+    kind: MarkerKind,
+    label: String,
+}
+
+#[derive(Clone, Copy)]
+enum MarkerKind {
+    Banana,
+    Shell,
+}
+
+/// A self-contained HTML page charting a race from its log: the cars' positions, speeds and
+/// balances turn by turn, the prices of the items, and where bananas were dropped and shells hit.
+pub fn html(log: &RaceLog) -> String {
+    let names = &log.header.cars;
+    let color = |car: usize| COLORS[car % COLORS.len()];
+    // the state before every turn, then the state the race ended in
+    let states: Vec<(f64, &[Car])> = log
+        .turns
+        .iter()
+        .map(|turn| (turn.turn as f64, &turn.cars[..]))
+        .chain([(log.result.turn as f64, &log.result.cars[..])])
+        .collect();
+    let per_car = |value: &dyn Fn(&Car) -> f64| -> Vec<Series> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| Series {
+                name: name.clone(),
+                color: color(index),
+                points: states
+                    .iter()
+                    .filter_map(|(turn, cars)| cars.get(index).map(|car| (*turn, value(car))))
+                    .collect(),
+            })
+            .collect()
+    };
+
+    let mut markers = Vec::new();
+    for (index, turn) in log.turns.iter().enumerate() {
+        let after = states.get(index + 1).map_or(&[][..], |(_, cars)| *cars);
+        let name = |car: usize| names.get(car).map_or("?", String::as_str);
+        for action in &turn.actions {
+            if let Action::Banana { car } = *action {
+                markers.push(Marker {
+                    x: turn.turn as f64,
+                    y: turn.cars.get(car).map_or(0.0, |car| car.y as f64),
+                    kind: MarkerKind::Banana,
+                    label: format!("turn {}: {} dropped a banana", turn.turn, name(car)),
+                });
+            }
+        }
+        for event in &turn.events {
+            if let Event::ShellHit { car, target, .. } = *event {
+                markers.push(Marker {
+                    x: turn.turn as f64 + 1.0,
+                    y: after.get(target).map_or(0.0, |car| car.y as f64),
+                    kind: MarkerKind::Shell,
+                    label: format!(
+                        "turn {}: {}'s shell hit {}",
+                        turn.turn,
+                        name(car),
+                        name(target)
+                    ),
+                });
+            }
+        }
+    }
+
+    let prices = ["acceleration", "banana", "shell"]
+        .into_iter()
+        .enumerate()
+        .map(|(item, name)| Series {
+            name: name.to_string(),
+            color: COLORS[item],
+            points: log
+                .turns
+                .iter()
+                .filter_map(|turn| {
+                    let cost = (*turn.costs.get(item)?)?;
+                    Some((turn.turn as f64, cost as f64))
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+
+    let mut page = String::new();
+    let winner = log
+        .result
+        .winner
+        .and_then(|car| names.get(car))
+        .map_or("nobody".to_string(), |name| escape(name));
+    write!(
+        page,
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>portimao race report</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em auto; max-width: 800px; color: #222; }}
+table {{ border-collapse: collapse; }}
+th, td {{ padding: 0.2em 0.8em; text-align: right; }}
+th:first-child, td:first-child {{ text-align: left; }}
+svg {{ display: block; margin: 1em 0 2em; }}
+svg text {{ font-size: 12px; }}
+</style>
+</head>
+<body>
+<h1>Race report</h1>
+<p>Seed {seed}, {turns} turns, finish at {finish}. Won by <strong>{winner}</strong>.</p>
+"#,
+        seed = log.header.seed,
+        turns = log.turns.len(),
+        finish = log.header.config.finish_distance,
+        winner = winner,
+    )
+    .unwrap();
+
+    page.push_str(
+        "<table>\n<tr><th>car</th><th>position</th><th>speed</th><th>balance</th><th></th></tr>\n",
+    );
+    for (index, car) in log.result.cars.iter().enumerate() {
+        let status = if log.result.winner == Some(index) {
+            "winner"
+        } else if car.disqualified {
+            "disqualified"
+        } else {
+            ""
+        };
+        writeln!(
+            page,
+            r#"<tr style="color: {}"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            color(index),
+            escape(&car.name),
+            car.y,
+            car.speed,
+            car.balance,
+            status
+        )
+        .unwrap();
+    }
+    page.push_str("</table>\n");
+
+    let charts = [
+        ("Position", per_car(&|car| car.y as f64), markers),
+        ("Speed", per_car(&|car| car.speed as f64), Vec::new()),
+        ("Balance", per_car(&|car| car.balance as f64), Vec::new()),
+        ("Prices of one item", prices, Vec::new()),
+    ];
+    for (title, series, markers) in charts {
+        writeln!(page, "<h2>{}</h2>", title).unwrap();
+        page.push_str(&chart(&series, &markers));
+    }
+    page.push_str(
+        "<p>&#9650; banana dropped &nbsp; &#10006; shell hit (hover for details)</p>\n</body>\n</html>\n",
+    );
+
+    page
+}
+
+/// An SVG line chart of `series` from turn to turn, with `markers` on top.
+fn chart(series: &[Series], markers: &[Marker]) -> String {
+    let points = || series.iter().flat_map(|series| &series.points);
+    let x_min = points().map(|p| p.0).fold(f64::INFINITY, f64::min);
+    let x_max = points().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+    let (x_min, x_max) = if x_min < x_max {
+        (x_min, x_max)
+    } else {
+        (0.0, 1.0)
+    };
+    let y_step = tick_step(points().map(|p| p.1).fold(0.0, f64::max));
+    let y_max = (points().map(|p| p.1).fold(0.0, f64::max) / y_step)
+        .ceil()
+        .max(1.0)
+        * y_step;
+
+    let (left, right, top, bottom) = MARGIN;
+    let plot_width = WIDTH - left - right;
+    let plot_height = HEIGHT - top - bottom;
+    let x = |value: f64| left + (value - x_min) / (x_max - x_min) * plot_width;
+    let y = |value: f64| top + plot_height - value / y_max * plot_height;
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        WIDTH, HEIGHT, WIDTH, HEIGHT
+    )
+    .unwrap();
+
+    // axes, with gridlines at every tick
+    let mut tick = 0.0;
+    while tick <= y_max {
+        writeln!(
+            svg,
+            r##"<line x1="{left}" y1="{y:.1}" x2="{end}" y2="{y:.1}" stroke="#ddd"/><text x="{label}" y="{y:.1}" text-anchor="end" dominant-baseline="middle">{tick}</text>"##,
+            left = left,
+            end = left + plot_width,
+            y = y(tick),
+            label = left - 6.0,
+            tick = tick,
+        )
+        .unwrap();
+        tick += y_step;
+    }
+    let x_step = tick_step(x_max - x_min);
+    let mut tick = (x_min / x_step).ceil() * x_step;
+    while tick <= x_max {
+        writeln!(
+            svg,
+            r#"<text x="{:.1}" y="{}" text-anchor="middle">{}</text>"#,
+            x(tick),
+            top + plot_height + 18.0,
+            tick
+        )
+        .unwrap();
+        tick += x_step;
+    }
+    writeln!(
+        svg,
+        r##"<line x1="{left}" y1="{bottom}" x2="{end}" y2="{bottom}" stroke="#222"/><text x="{middle}" y="{label}" text-anchor="middle">turn</text>"##,
+        left = left,
+        end = left + plot_width,
+        bottom = top + plot_height,
+        middle = left + plot_width / 2.0,
+        label = HEIGHT - 4.0,
+    )
+    .unwrap();
+
+    for (index, series) in series.iter().enumerate() {
+        let path: Vec<String> = series
+            .points
+            .iter()
+            .map(|&(px, py)| format!("{:.1},{:.1}", x(px), y(py)))
+            .collect();
+        writeln!(
+            svg,
+            r#"<polyline fill="none" stroke="{color}" stroke-width="2" points="{points}"/><text x="{legend_x}" y="{legend_y}" fill="{color}">{name}</text>"#,
+            color = series.color,
+            points = path.join(" "),
+            legend_x = left + plot_width + 12.0,
+            legend_y = top + 16.0 * index as f64 + 10.0,
+            name = escape(&series.name),
+        )
+        .unwrap();
+    }
+
+    for marker in markers {
+        let (mx, my) = (x(marker.x), y(marker.y));
+        let (shape, element) = match marker.kind {
+            MarkerKind::Banana => (
+                format!(
+                    r##"<polygon points="{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}" fill="#e6c200" stroke="#222" stroke-width="0.5">"##,
+                    mx,
+                    my - 6.0,
+                    mx - 5.0,
+                    my + 4.0,
+                    mx + 5.0,
+                    my + 4.0
+                ),
+                "polygon",
+            ),
+            MarkerKind::Shell => (
+                format!(
+                    r##"<path d="M{:.1},{:.1} l8,8 m0,-8 l-8,8" stroke="#c00" stroke-width="2.5">"##,
+                    mx - 4.0,
+                    my - 4.0
+                ),
+                "path",
+            ),
+        };
+        writeln!(
+            svg,
+            "{}<title>{}</title></{}>",
+            shape,
+            escape(&marker.label),
+            element
+        )
+        .unwrap();
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// A round step between ticks that puts about five ticks over `range`.
+fn tick_step(range: f64) -> f64 {
+    if range <= 0.0 {
+        return 1.0;
+    }
+    let rough = range / 5.0;
+    let magnitude = 10f64.powf(rough.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|factor| factor * magnitude)
+        .find(|&step| step >= rough)
+        .unwrap_or(10.0 * magnitude);
+    step.max(1.0)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RaceConfig;
+    use crate::game::Game;
+    use crate::runtime::LuaRunner;
+
+    #[test]
+    fn charts_the_race() {
+        let script = r#"
+            function takeYourTurn()
+                GameState:buy_acceleration(2)
+                if math.random() < 0.3 then GameState:buy_banana() end
+                if math.random() < 0.3 then GameState:buy_shell(1) end
+            end
+        "#;
+        let mut game = Game::with_seed(RaceConfig::default(), 3);
+        for name in ["Alice", "<Bob>"] {
+            let runner = LuaRunner::new(script.to_string());
+            game.register(Car::new(Box::new(runner), name.to_string()))
+                .unwrap();
+        }
+        game.start().unwrap();
+        game.race().unwrap();
+        let log = game.race_log();
+
+        let page = html(&log);
+        assert!(page.starts_with("<!DOCTYPE html>"));
+        assert_eq!(page.matches("<svg").count(), 4);
+        assert!(page.contains("&lt;Bob&gt;") && !page.contains("<Bob>"));
+
+        let bananas = log
+            .turns
+            .iter()
+            .flat_map(|turn| &turn.actions)
+            .filter(|action| matches!(action, Action::Banana { .. }))
+            .count();
+        let hits = log
+            .turns
+            .iter()
+            .flat_map(|turn| &turn.events)
+            .filter(|event| matches!(event, Event::ShellHit { .. }))
+            .count();
+        assert!(bananas > 0 && hits > 0);
+        assert_eq!(page.matches("dropped a banana").count(), bananas);
+        assert_eq!(page.matches("'s shell hit").count(), hits);
+    }
+
+    #[test]
+    fn tick_steps_are_round() {
+        assert_eq!(tick_step(1000.0), 200.0);
+        assert_eq!(tick_step(87.0), 20.0);
+        assert_eq!(tick_step(17500.0), 5000.0);
+        assert_eq!(tick_step(3.0), 1.0);
+    }
+}
@@ -0,0 +1,649 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use mlua::prelude::LuaError;
+use mlua::{
+    Function, HookTriggers, IntoLua, Lua, LuaOptions, StdLib, Table, UserData, Value, Variadic,
+};
+
+use crate::action::Action;
+use crate::car::Car;
+use crate::config::{ItemPricing, RaceConfig};
+use crate::error::GameError;
+use crate::game::{Game, Quote};
+use crate::log::Log;
+use crate::runtime::{Budget, Penalty, Runner};
+
+/// How often the instruction budget is checked.
+const HOOK_INTERVAL: u32 = 1000;
+
+/// Globals of the loaded libraries that reach outside the sandbox.
+const UNSAFE_GLOBALS: [&str; 4] = ["dofile", "loadfile", "load", "collectgarbage"];
+
+/// Runs a Lua script defining a global `takeYourTurn` function.
+///
+/// The script is loaded once, on the car's first turn, into a Lua that lives for the whole race,
+/// so its globals and upvalues persist between turns. If it defines a global `init` function,
+/// that is called with the race's config right after loading.
+///
+/// Scripts run in a sandbox with only the `coroutine`, `table`, `string`, `utf8` and `math`
+/// libraries and without `dofile`, `loadfile`, `load` and `collectgarbage`. `print` writes to
+/// stderr, so that stdout only carries what the engine writes there, such as a streamed log.
+#[derive(Debug)]
+pub struct LuaRunner {
+    script: String,
+    name: String,
+    limits: LuaLimits,
+    vm: Option<Vm>,
+}
+
+/// A loaded script, the count of instructions it ran this turn and when it must stop by.
+#[derive(Debug)]
+struct Vm {
+    lua: Lua,
+    executed: Arc<AtomicU64>,
+    deadline: Arc<Mutex<Option<Instant>>>,
+}
+
+/// The budgets a Lua script runs under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LuaLimits {
+    /// Instructions a turn may run, loading the script included.
+    pub instructions: u64,
+    /// Bytes of memory the script may allocate.
+    pub memory: usize,
+    /// What happens to a car that goes over either budget.
+    pub penalty: Penalty,
+}
+
+impl Default for LuaLimits {
+    fn default() -> Self {
+        Self {
+            instructions: 1_000_000,
+            memory: 16 * 1024 * 1024,
+            penalty: Penalty::SkipTurn,
+        }
+    }
+}
+
+impl LuaRunner {
+    /// Creates a runner for the script's source, with the default limits.
+    pub fn new(script: String) -> Self {
+        Self {
+            script,
+            name: "script".to_string(),
+            limits: LuaLimits::default(),
+            vm: None,
+        }
+    }
+
+    /// Names the script in its error messages, such as after the file it was read from.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Sets the budgets the script runs under.
+    pub fn with_limits(mut self, limits: LuaLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Creates a sandboxed Lua with the memory budget set and a hook counting the instructions
+    /// it runs, which also stops the script at the turn's deadline.
+    fn sandbox(&self) -> Result<Vm, LuaError> {
+        let lua = Lua::new_with(
+            StdLib::COROUTINE | StdLib::TABLE | StdLib::STRING | StdLib::UTF8 | StdLib::MATH,
+            LuaOptions::default(),
+        )?;
+
+        for name in UNSAFE_GLOBALS {
+            lua.globals().raw_remove(name)?;
+        }
+        let print = lua.create_function(|lua, values: Variadic<Value>| {
+            let tostring: Function = lua.globals().get("tostring")?;
+            let texts = values
+                .into_iter()
+                .map(|value| tostring.call::<_, String>(value))
+                .collect::<Result<Vec<_>, _>>()?;
+            eprintln!("{}", texts.join("\t"));
+            Ok(())
+        })?;
+        lua.globals().set("print", print)?;
+        lua.set_memory_limit(self.limits.memory)?;
+
+        let executed = Arc::new(AtomicU64::new(0));
+        let counter = executed.clone();
+        let deadline = Arc::new(Mutex::new(None));
+        let stop_at = deadline.clone();
+        let limit = self.limits.instructions;
+        lua.set_hook(
+            HookTriggers::new().every_nth_instruction(HOOK_INTERVAL),
+            move |_, _| {
+                let executed = counter.fetch_add(HOOK_INTERVAL as u64, Ordering::Relaxed);
+                if executed + HOOK_INTERVAL as u64 > limit {
+                    return Err(LuaError::RuntimeError(
+                        "instruction limit exceeded".to_string(),
+                    ));
+                }
+                if stop_at
+                    .lock()
+                    .unwrap()
+                    .is_some_and(|deadline| Instant::now() >= deadline)
+                {
+                    // the car is out of the race already, this only frees its thread
+                    return Err(LuaError::RuntimeError("time budget exceeded".to_string()));
+                }
+                Ok(())
+            },
+        );
+
+        Ok(Vm {
+            lua,
+            executed,
+            deadline,
+        })
+    }
+
+    /// Loads the script into a new sandbox and calls its `init` function, if any.
+    fn load(&self, state: &Arc<Mutex<Game>>) -> Result<Vm, GameError> {
+        let vm = self
+            .sandbox()
+            .map_err(|e| GameError::Script(format!("Failed to create the Lua sandbox: {}", e)))?;
+        *vm.deadline.lock().unwrap() = state.lock().unwrap().deadline();
+
+        vm.lua
+            .globals()
+            .set("GameState", GameState(state.clone()))
+            .map_err(|e| GameError::Script(e.to_string()))?;
+
+        // Lua seeds `math.random` from the clock otherwise
+        let seed = state.lock().unwrap().turn_seed();
+        self.check(
+            vm.lua
+                .load(format!("math.randomseed({})", seed as i64))
+                .exec(),
+            &vm.executed,
+            "Failed to seed math.random",
+        )?;
+
+        self.check(
+            vm.lua
+                .load(&self.script)
+                .set_name(format!("@{}", self.name))
+                .exec(),
+            &vm.executed,
+            "Failed to load Lua script",
+        )?;
+
+        self.init(&vm, state)?;
+
+        Ok(vm)
+    }
+
+    fn init(&self, vm: &Vm, state: &Arc<Mutex<Game>>) -> Result<(), GameError> {
+        let init: Option<Function> = self.check(
+            vm.lua.globals().get("init"),
+            &vm.executed,
+            "Error on getting init function",
+        )?;
+
+        if let Some(init) = init {
+            let config = state.lock().unwrap().config().clone();
+            self.check(
+                init.call::<_, ()>(config),
+                &vm.executed,
+                "Error on calling init function",
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn take_your_turn(&self, vm: &Vm, state: &Arc<Mutex<Game>>) -> Result<(), GameError> {
+        vm.lua
+            .globals()
+            .set("GameState", GameState(state.clone()))
+            .map_err(|e| GameError::Script(e.to_string()))?;
+
+        let take_your_turn: Function = self.check(
+            vm.lua.globals().get("takeYourTurn"),
+            &vm.executed,
+            "Error on getting takeYourTurn function",
+        )?;
+
+        self.check(
+            take_your_turn.call::<_, ()>(()),
+            &vm.executed,
+            "Error on calling takeYourTurn function",
+        )
+    }
+
+    /// Turns the outcome of running the script into the runner's result. Going over the
+    /// instruction budget counts even when the script caught the error with `pcall`.
+    fn check<T>(
+        &self,
+        result: Result<T, LuaError>,
+        executed: &AtomicU64,
+        context: &str,
+    ) -> Result<T, GameError> {
+        let penalty = self.limits.penalty;
+        if executed.load(Ordering::Relaxed) > self.limits.instructions {
+            return Err(GameError::BudgetExceeded {
+                budget: Budget::Instructions(self.limits.instructions),
+                penalty,
+            });
+        }
+
+        result.map_err(|e| {
+            if is_memory_error(&e) {
+                GameError::BudgetExceeded {
+                    budget: Budget::Memory(self.limits.memory),
+                    penalty,
+                }
+            } else {
+                GameError::Script(format!("{}: {}", context, e))
+            }
+        })
+    }
+}
+
+impl Runner for LuaRunner {
+    fn run(&mut self, game: &mut Game) -> Result<(), GameError> {
+        let state = Arc::new(Mutex::new(game.clone()));
+
+        let vm = match self.vm.take() {
+            Some(vm) => {
+                *vm.deadline.lock().unwrap() = game.deadline();
+                vm
+            }
+            None => self.load(&state)?,
+        };
+
+        let result = self.take_your_turn(&vm, &state);
+
+        vm.executed.store(0, Ordering::Relaxed);
+        self.vm = Some(vm);
+        result?;
+
+        let new_state = state.lock().unwrap();
+        game.clone_from(&new_state);
+
+        Ok(())
+    }
+
+    /// Loads the script as it would be for a race, into a race of its own with the default
+    /// config, so that it can read `GameState` as it loads.
+    fn validate(&self) -> Result<(), GameError> {
+        let mut game = Game::with_seed(RaceConfig::default(), 0);
+        game.register(Car::new(self.box_clone(), "Validation".to_string()))?;
+        let Vm { lua, .. } = self.load(&Arc::new(Mutex::new(game)))?;
+
+        lua.globals()
+            .get::<_, Function>("takeYourTurn")
+            .map_err(|e| GameError::Script(format!("Missing takeYourTurn function: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// The new runner loads the script again in a Lua of its own.
+    fn box_clone(&self) -> Box<dyn Runner> {
+        Box::new(
+            Self::new(self.script.clone())
+                .with_name(self.name.clone())
+                .with_limits(self.limits),
+        )
+    }
+}
+
+fn is_memory_error(error: &LuaError) -> bool {
+    match error {
+        LuaError::MemoryError(_) => true,
+        LuaError::CallbackError { cause, .. } => is_memory_error(cause),
+        _ => false,
+    }
+}
+
+struct GameState(Arc<Mutex<Game>>);
+
+impl UserData for GameState {
+    fn add_fields<'lua, F: mlua::prelude::LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("config", |_, this| {
+            Ok(this.0.lock().unwrap().config().clone())
+        });
+        fields.add_field_method_get("turns", |_, this| Ok(this.0.lock().unwrap().turns()));
+        fields.add_field_method_get("cars", |_, this| Ok(this.0.lock().unwrap().cars().to_vec()));
+        fields.add_field_method_get("bananas", |_, this| {
+            Ok(this.0.lock().unwrap().bananas().to_vec())
+        });
+        fields.add_field_method_get("index", |_, this| {
+            let index = this.0.lock().unwrap().get_index() + 1;
+            Ok(index)
+        });
+        fields.add_field_method_get("seed", |_, this| {
+            let seed = this.0.lock().unwrap().turn_seed();
+            Ok(seed)
+        });
+        fields.add_field_method_get("logs", |_, this| {
+            let logs = this.0.lock().unwrap().logs().to_vec();
+            Ok(logs)
+        });
+    }
+    fn add_methods<'lua, M: mlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method_mut("buy_acceleration", |_, user_data, amount: u32| {
+            Ok(user_data.0.lock().unwrap().buy_acceleration(amount))
+        });
+
+        methods.add_method_mut("buy_banana", |_, user_data, (): ()| {
+            Ok(user_data.0.lock().unwrap().buy_banana())
+        });
+
+        methods.add_method_mut("buy_shell", |_, user_data, amount: u32| {
+            Ok(user_data.0.lock().unwrap().buy_shell(amount))
+        });
+
+        methods.add_method("get_accelerate_cost", |_, user_data, amount: u32| {
+            let lock = user_data.0.lock().unwrap();
+            let cost = lock.get_accelerate_cost(amount);
+            Ok(cost)
+        });
+
+        methods.add_method("get_banana_cost", |_, user_data, (): ()| {
+            let lock = user_data.0.lock().unwrap();
+            let cost = lock.get_banana_cost();
+            Ok(cost)
+        });
+        methods.add_method("get_shell_cost", |_, user_data, amount: u32| {
+            let lock = user_data.0.lock().unwrap();
+            let cost = lock.get_shell_cost(amount);
+            Ok(cost)
+        });
+
+        methods.add_method("quote", |_, user_data, items: Table| {
+            let mut amounts = [0; 3];
+            for pair in items.pairs::<String, u32>() {
+                let (item, amount) = pair?;
+                let index = match item.as_str() {
+                    "acceleration" => Action::ACCELERATION,
+                    "banana" => Action::BANANA,
+                    "shell" => Action::SHELL,
+                    _ => return Err(LuaError::RuntimeError(format!("no item {:?}", item))),
+                };
+                amounts[index] = amount;
+            }
+            if amounts[Action::BANANA] > 1 {
+                return Err(LuaError::RuntimeError(
+                    "a car can only drop one banana where it stands".to_string(),
+                ));
+            }
+            let lock = user_data.0.lock().unwrap();
+            let quote = lock.quote(
+                amounts[Action::ACCELERATION],
+                amounts[Action::BANANA] == 1,
+                amounts[Action::SHELL],
+            );
+            Ok(quote)
+        });
+
+        methods.add_method(
+            "max_affordable_acceleration",
+            |_, user_data, budget: u128| {
+                let lock = user_data.0.lock().unwrap();
+                let amount = lock.max_affordable_acceleration(budget);
+                Ok(amount)
+            },
+        );
+    }
+}
+
+impl<'lua> IntoLua<'lua> for Quote {
+    fn into_lua(self, lua: &'lua Lua) -> color_eyre::Result<Value<'lua>, LuaError> {
+        let table = lua.create_table()?;
+        table.set("acceleration", self.acceleration)?;
+        table.set("banana", self.banana)?;
+        table.set("shell", self.shell)?;
+        table.set("total", self.total)?;
+        Ok(Value::Table(table))
+    }
+}
+
+impl<'lua> IntoLua<'lua> for Car {
+    fn into_lua(self, lua: &'lua Lua) -> color_eyre::Result<Value<'lua>, LuaError> {
+        let table = lua.create_table()?;
+        table.set("balance", self.balance)?;
+        table.set("speed", self.speed)?;
+        table.set("y", self.y)?;
+        Ok(Value::Table(table))
+    }
+}
+
+impl<'lua> IntoLua<'lua> for RaceConfig {
+    fn into_lua(self, lua: &'lua Lua) -> color_eyre::Result<Value<'lua>, LuaError> {
+        let table = lua.create_table()?;
+        table.set("min_players", self.min_players)?;
+        table.set("max_players", self.max_players)?;
+        table.set("finish_distance", self.finish_distance)?;
+        table.set("max_turns", self.max_turns)?;
+        table.set("starting_balance", self.starting_balance)?;
+        table.set("acceleration", self.acceleration)?;
+        table.set("banana", self.banana)?;
+        table.set("shell", self.shell)?;
+        Ok(Value::Table(table))
+    }
+}
+
+impl<'lua> IntoLua<'lua> for ItemPricing {
+    fn into_lua(self, lua: &'lua Lua) -> color_eyre::Result<Value<'lua>, LuaError> {
+        let table = lua.create_table()?;
+        match self {
+            ItemPricing::Exponential(model) => {
+                table.set("model", "exponential")?;
+                table.set("target_price", model.target_price)?;
+                table.set("per_turn_decrease", model.per_turn_decrease)?;
+                table.set("sell_per_turn", model.sell_per_turn)?;
+            }
+            ItemPricing::Linear(model) => {
+                table.set("model", "linear")?;
+                table.set("target_price", model.target_price)?;
+                table.set("per_turn_decrease", model.per_turn_decrease)?;
+                table.set("sell_per_turn", model.sell_per_turn)?;
+            }
+            ItemPricing::Logistic(model) => {
+                table.set("model", "logistic")?;
+                table.set("target_price", model.target_price)?;
+                table.set("per_turn_decrease", model.per_turn_decrease)?;
+                table.set("max_sellable", model.max_sellable)?;
+                table.set("time_scale", model.time_scale)?;
+            }
+            ItemPricing::Fixed(model) => {
+                table.set("model", "fixed")?;
+                table.set("price", model.price)?;
+            }
+        }
+        Ok(Value::Table(table))
+    }
+}
+
+impl<'lua> IntoLua<'lua> for Action {
+    fn into_lua(self, lua: &'lua Lua) -> color_eyre::Result<Value<'lua>, LuaError> {
+        let table = lua.create_table()?;
+        // cars are numbered from 1, like `GameState.index`
+        table.set("car", self.car() + 1)?;
+        match self {
+            Action::Acceleration { amount, .. } => {
+                table.set("type", "acceleration")?;
+                table.set("amount", amount)?;
+            }
+            Action::Banana { .. } => {
+                table.set("type", "banana")?;
+            }
+            Action::Shell { amount, .. } => {
+                table.set("type", "shell")?;
+                table.set("amount", amount)?;
+            }
+        }
+
+        Ok(Value::Table(table))
+    }
+}
+
+impl<'lua> IntoLua<'lua> for Log {
+    fn into_lua(self, lua: &'lua Lua) -> color_eyre::Result<Value<'lua>, LuaError> {
+        let table = lua.create_table()?;
+        table.set("turn", self.turn)?;
+        table.set("actions", self.actions)?;
+        table.set("bananas", self.bananas)?;
+        // unaffordable, as `get_*_cost` has it
+        let costs: Vec<u128> = self
+            .costs
+            .iter()
+            .map(|cost| cost.unwrap_or(u128::MAX))
+            .collect();
+        table.set("costs", costs)?;
+        table.set("car", self.cars)?;
+        table.set("actions_sold", self.actions_sold)?;
+        Ok(Value::Table(table))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pricing::{FixedPrice, LinearVrgda};
+    use crate::runtime::tests::{persists_between_turns, race};
+
+    #[test]
+    fn validate() {
+        let runner = |script: &str| LuaRunner::new(script.to_string());
+
+        assert!(runner("function takeYourTurn() end").validate().is_ok());
+        assert!(runner("function takeYourTurn(").validate().is_err());
+        assert!(runner("local x = 1").validate().is_err());
+        assert!(runner("io.write('hi') function takeYourTurn() end")
+            .validate()
+            .is_err());
+
+        // scripts can read the race as they load
+        let top = "local speed = GameState.seed % 3 function takeYourTurn() end";
+        assert_eq!(runner(top).validate(), Ok(()));
+        match runner("error('boom')").with_name("top.lua").validate() {
+            Err(GameError::Script(message)) => assert!(message.contains("top.lua:1: boom")),
+            other => panic!("expected a script error, got {:?}", other),
+        }
+    }
+
+    fn run(script: &str) -> Result<(), GameError> {
+        let limits = LuaLimits {
+            instructions: 100_000,
+            memory: 1024 * 1024,
+            penalty: Penalty::Disqualify,
+        };
+        let mut runner = LuaRunner::new(script.to_string()).with_limits(limits);
+
+        runner.run(&mut race(RaceConfig::default(), &runner))
+    }
+
+    #[test]
+    fn sandbox() {
+        assert!(run("function takeYourTurn() end").is_ok());
+
+        for script in [
+            "function takeYourTurn() os.exit(1) end",
+            "function takeYourTurn() require('os') end",
+            "function takeYourTurn() load('return 1')() end",
+        ] {
+            assert!(
+                matches!(run(script), Err(GameError::Script(_))),
+                "{}",
+                script
+            );
+        }
+    }
+
+    #[test]
+    fn quotes() {
+        // on turn 1, the nth acceleration costs 100 + 10 n
+        let config = RaceConfig {
+            starting_balance: 1_000,
+            acceleration: ItemPricing::Linear(LinearVrgda {
+                target_price: 100,
+                per_turn_decrease: 0.2,
+                sell_per_turn: 2.0,
+            }),
+            banana: ItemPricing::Fixed(FixedPrice { price: 200 }),
+            shell: ItemPricing::Fixed(FixedPrice { price: 50 }),
+            ..RaceConfig::default()
+        };
+        let script = r#"
+            local function same(quote, acceleration, banana, shell, total)
+                return quote.acceleration == acceleration and quote.banana == banana
+                    and quote.shell == shell and quote.total == total
+            end
+            local unaffordable = GameState:get_accelerate_cost(1e9)
+            assert(unaffordable > 1e38)
+
+            function takeYourTurn()
+                assert(same(GameState:quote{acceleration = 3, shell = 2}, 110 + 120 + 130, 0, 100, 460))
+
+                -- quotes follow the turn's purchases, and what's left of the balance
+                assert(GameState:buy_acceleration(2))
+                assert(same(GameState:quote{acceleration = 1, banana = 1}, 130, 200, 0, 330))
+                local quote = GameState:quote{acceleration = 4, banana = 1, shell = 2}
+                assert(same(quote, 130 + 140 + 150 + 160, unaffordable, 100, unaffordable))
+
+                assert(GameState:max_affordable_acceleration(1e9) == 5)
+                assert(GameState:max_affordable_acceleration(300) == 2)
+                assert(GameState:max_affordable_acceleration(0) == 0)
+
+                assert(not pcall(GameState.quote, GameState, {shells = 1}))
+                assert(not pcall(GameState.quote, GameState, {banana = 2}))
+            end
+        "#;
+        let mut runner = LuaRunner::new(script.to_string());
+        assert_eq!(runner.run(&mut race(config, &runner)), Ok(()));
+    }
+
+    #[test]
+    fn state_persists_between_turns() {
+        let script = r#"
+            local inits, turns = 0, 0
+            function init(config)
+                inits = inits + 1
+                finish = config.finish_distance
+            end
+            function takeYourTurn()
+                assert(inits == 1 and finish == GameState.config.finish_distance)
+                turns = turns + 1
+                GameState:buy_acceleration(turns)
+            end
+        "#;
+        // a clone starts over with a fresh Lua, which calls `init` again
+        persists_between_turns(Box::new(LuaRunner::new(script.to_string())));
+    }
+
+    #[test]
+    fn limits() {
+        let instructions = GameError::BudgetExceeded {
+            budget: Budget::Instructions(100_000),
+            penalty: Penalty::Disqualify,
+        };
+        assert_eq!(
+            run("function takeYourTurn() while true do end end"),
+            Err(instructions.clone())
+        );
+        assert_eq!(
+            run("function takeYourTurn() while true do pcall(function() end) end end"),
+            Err(instructions.clone())
+        );
+        assert_eq!(run("while true do end"), Err(instructions));
+
+        assert_eq!(
+            run("function takeYourTurn() local s = string.rep('x', 1e7) end"),
+            Err(GameError::BudgetExceeded {
+                budget: Budget::Memory(1024 * 1024),
+                penalty: Penalty::Disqualify,
+            })
+        );
+    }
+}
@@ -1,8 +1,106 @@
+use std::fmt::{self, Debug, Display};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::GameError;
 use crate::game::Game;
 
 mod lua;
+mod native;
 mod process_based;
+mod wasm;
+
+pub use lua::{LuaLimits, LuaRunner};
+pub use native::{NativeRunner, Purchases, Strategy, View};
+pub use process_based::ProcessRunner;
+pub use wasm::{WasmLimits, WasmRunner};
+
+/// Decides a car's purchases for a turn. Every car's runner plays its turn on a thread of its own,
+/// at the same time as the others.
+pub trait Runner: Debug + Send + 'static {
+    /// Plays the turn of the car at `game.get_index()` by calling the game's `buy_*` methods on
+    /// `game`, which is the car's own copy of the race. A car whose turn fails buys nothing that
+    /// turn; `GameError::BudgetExceeded` also gets it the error's penalty.
+    fn run(&mut self, game: &mut Game) -> Result<(), GameError>;
+
+    /// Checks the car's program before the race starts.
+    fn validate(&self) -> Result<(), GameError> {
+        Ok(())
+    }
+
+    /// Returns a runner for the same program.
+    fn box_clone(&self) -> Box<dyn Runner>;
+}
+
+impl Clone for Box<dyn Runner> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// A resource a car's program is limited on, with its limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Budget {
+    /// Instructions per turn.
+    Instructions(u64),
+    /// WebAssembly fuel per turn.
+    Fuel(u64),
+    /// Wall-clock time per turn.
+    Time(Duration),
+    /// Bytes of memory.
+    Memory(usize),
+}
+
+/// What happens to a car that goes over a budget.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Penalty {
+    /// The car buys nothing this turn.
+    #[default]
+    SkipTurn,
+    /// The car stops where it is and plays no more turns.
+    Disqualify,
+}
+
+impl Display for Budget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Instructions(limit) => write!(f, "{} instructions", limit),
+            Self::Fuel(limit) => write!(f, "{} fuel", limit),
+            Self::Time(limit) => write!(f, "{:?} per turn", limit),
+            Self::Memory(limit) => write!(f, "{} bytes of memory", limit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::car::Car;
+    use crate::config::RaceConfig;
+
+    /// A race with `config` whose only car, Alice, runs a clone of `runner`.
+    pub(super) fn race(config: RaceConfig, runner: &dyn Runner) -> Game {
+        let mut game = Game::with_seed(config, 0);
+        game.register(Car::new(runner.box_clone(), "Alice".to_string()))
+            .unwrap();
+        game
+    }
+
+    /// Checks that `runner`, which buys as many accelerations as the turns it played, keeps its
+    /// program's state between turns, and that its clones start over.
+    pub(super) fn persists_between_turns(mut runner: Box<dyn Runner>) {
+        let mut game = race(RaceConfig::default(), &*runner);
+        let balance = game.cars()[0].balance;
+
+        let first = game.get_accelerate_cost(1);
+        assert_eq!(runner.run(&mut game), Ok(()));
+        let second = game.get_accelerate_cost(2);
+        assert_eq!(runner.run(&mut game), Ok(()));
+        assert_eq!(game.cars()[0].balance, balance - first - second);
 
-pub trait Runner {
-    fn run(&mut self, game: &mut Game) -> Result<(), String>;
-}
\ No newline at end of file
+        let third = game.get_accelerate_cost(1);
+        assert_eq!(runner.box_clone().run(&mut game), Ok(()));
+        assert_eq!(game.cars()[0].balance, balance - first - second - third);
+    }
+}
@@ -0,0 +1,245 @@
+use std::fmt::{self, Debug};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+
+use crate::car::Car;
+use crate::config::RaceConfig;
+use crate::error::GameError;
+use crate::game::Game;
+use crate::log::Log;
+use crate::runtime::Runner;
+
+/// A car written in Rust, played in-process.
+///
+/// A strategy is created on the car's first turn and kept for the whole race, so it can keep
+/// state between turns in its own fields. It runs in the engine's process, so it must not write
+/// to stdout, which `portimao race --stream -` reserves for the log: stderr is left for debugging.
+pub trait Strategy: Send {
+    /// Called once, before the car's first turn.
+    fn init(&mut self, _config: &RaceConfig) {}
+
+    /// Plays a turn: `view` is the race as the turn started and `purchases` buys for the car.
+    fn take_your_turn(&mut self, view: &View, purchases: &mut Purchases);
+}
+
+/// The race as a car's turn started, the same as a Lua script's `GameState`.
+pub struct View<'a> {
+    game: &'a Game,
+}
+
+/// Buys items for the car whose turn is being played.
+pub struct Purchases<'a> {
+    game: &'a mut Game,
+}
+
+/// Runs a [`Strategy`], made by a function so that every race gets a fresh one.
+pub struct NativeRunner {
+    new_strategy: Arc<dyn Fn() -> Box<dyn Strategy> + Send + Sync>,
+    strategy: Option<Box<dyn Strategy>>,
+}
+
+impl NativeRunner {
+    /// Creates a runner playing the strategies `new_strategy` makes.
+    pub fn new<S, F>(new_strategy: F) -> Self
+    where
+        S: Strategy + 'static,
+        F: Fn() -> S + Send + Sync + 'static,
+    {
+        Self {
+            new_strategy: Arc::new(move || Box::new(new_strategy())),
+            strategy: None,
+        }
+    }
+}
+
+impl Runner for NativeRunner {
+    /// A strategy that panics loses its turn and starts over on the car's next turn.
+    fn run(&mut self, game: &mut Game) -> Result<(), GameError> {
+        let before = game.clone();
+        let strategy = &mut self.strategy;
+        let new_strategy = &self.new_strategy;
+
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            let strategy = strategy.get_or_insert_with(|| {
+                let mut strategy = new_strategy();
+                strategy.init(before.config());
+                strategy
+            });
+            strategy.take_your_turn(&View { game: &before }, &mut Purchases { game });
+        }))
+        .map_err(|payload| {
+            self.strategy = None;
+            game.clone_from(&before);
+
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            GameError::Script(format!("Strategy panicked: {}", message))
+        })
+    }
+
+    fn box_clone(&self) -> Box<dyn Runner> {
+        Box::new(Self {
+            new_strategy: self.new_strategy.clone(),
+            strategy: None,
+        })
+    }
+}
+
+impl Debug for NativeRunner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeRunner")
+            .field("started", &self.strategy.is_some())
+            .finish()
+    }
+}
+
+impl View<'_> {
+    /// The rules of the race.
+    pub fn config(&self) -> &RaceConfig {
+        self.game.config()
+    }
+
+    /// The turn being played, starting at 1.
+    pub fn turns(&self) -> usize {
+        self.game.turns()
+    }
+
+    /// The cars, in the order they registered.
+    pub fn cars(&self) -> &[Car] {
+        self.game.cars()
+    }
+
+    /// Positions of the bananas on the track, in increasing order.
+    pub fn bananas(&self) -> &[u32] {
+        self.game.bananas()
+    }
+
+    /// The index of the car playing, in `cars`.
+    pub fn index(&self) -> usize {
+        self.game.get_index()
+    }
+
+    /// The car's seed for this turn.
+    pub fn seed(&self) -> u64 {
+        self.game.turn_seed()
+    }
+
+    /// The race's log.
+    pub fn logs(&self) -> &[Log] {
+        self.game.logs()
+    }
+
+    /// What `amount` accelerations cost as the turn started.
+    pub fn get_accelerate_cost(&self, amount: u32) -> u128 {
+        self.game.get_accelerate_cost(amount)
+    }
+
+    /// What a banana cost as the turn started.
+    pub fn get_banana_cost(&self) -> u128 {
+        self.game.get_banana_cost()
+    }
+
+    /// What `amount` shells cost as the turn started.
+    pub fn get_shell_cost(&self, amount: u32) -> u128 {
+        self.game.get_shell_cost(amount)
+    }
+}
+
+impl Purchases<'_> {
+    /// See [`Game::buy_acceleration`].
+    pub fn buy_acceleration(&mut self, amount: u32) -> bool {
+        self.game.buy_acceleration(amount)
+    }
+
+    /// See [`Game::buy_banana`].
+    pub fn buy_banana(&mut self) -> bool {
+        self.game.buy_banana()
+    }
+
+    /// See [`Game::buy_shell`].
+    pub fn buy_shell(&mut self, amount: u32) -> bool {
+        self.game.buy_shell(amount)
+    }
+
+    /// What the car has left to spend.
+    pub fn balance(&self) -> u128 {
+        self.game.cars()[self.game.get_index()].balance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::tests::{persists_between_turns, race};
+    use crate::runtime::LuaRunner;
+
+    /// Buys one more acceleration every turn.
+    #[derive(Default)]
+    struct Ramp {
+        inits: usize,
+        turns: u32,
+    }
+
+    impl Strategy for Ramp {
+        fn init(&mut self, _config: &RaceConfig) {
+            self.inits += 1;
+        }
+
+        fn take_your_turn(&mut self, _view: &View, purchases: &mut Purchases) {
+            assert_eq!(self.inits, 1);
+            self.turns += 1;
+            purchases.buy_acceleration(self.turns);
+        }
+    }
+
+    fn game() -> Game {
+        race(RaceConfig::default(), &NativeRunner::new(Ramp::default))
+    }
+
+    #[test]
+    fn strategy_persists_between_turns() {
+        // a clone starts over with a new strategy, whose `init` is called again
+        persists_between_turns(Box::new(NativeRunner::new(Ramp::default)));
+    }
+
+    struct Panics;
+
+    impl Strategy for Panics {
+        fn take_your_turn(&mut self, _view: &View, purchases: &mut Purchases) {
+            purchases.buy_acceleration(1);
+            panic!("out of ideas");
+        }
+    }
+
+    #[test]
+    fn panics_lose_the_turn() {
+        let mut game = game();
+        let balance = game.cars()[0].balance;
+
+        let error = NativeRunner::new(|| Panics).run(&mut game).unwrap_err();
+        assert_eq!(
+            error,
+            GameError::Script("Strategy panicked: out of ideas".to_string())
+        );
+        assert_eq!(game.cars()[0].balance, balance);
+    }
+
+    #[test]
+    fn races_against_lua_cars() {
+        let mut game = game();
+        let script = "function takeYourTurn() GameState:buy_acceleration(1) end";
+        game.register(Car::new(
+            Box::new(LuaRunner::new(script.to_string())),
+            "Bob".to_string(),
+        ))
+        .unwrap();
+
+        game.start().unwrap();
+        game.race().unwrap();
+
+        assert_eq!(game.winner().unwrap().name, "Alice");
+    }
+}
@@ -0,0 +1,261 @@
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::car::Car;
+use crate::config::RaceConfig;
+use crate::error::GameError;
+use crate::game::Game;
+use crate::runtime::Runner;
+
+const TURN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Runs a car executable that is sent the race as one JSON line per turn on its stdin and
+/// answers each line with a JSON list of purchases on its stdout.
+///
+/// The process is started on the first turn and kept for the whole race. A turn fails when the
+/// process does not answer within the timeout, exits, or answers with something that is not a
+/// list of purchases; the process is then killed and started again on the car's next turn.
+#[derive(Debug)]
+pub struct ProcessRunner {
+    program: PathBuf,
+    args: Vec<String>,
+    timeout: Duration,
+    process: Option<Process>,
+}
+
+/// The state sent to the car at the start of each turn.
+#[derive(Debug, Serialize)]
+struct TurnState<'a> {
+    config: &'a RaceConfig,
+    turns: usize,
+    cars: &'a [Car],
+    bananas: &'a [u32],
+    index: usize,
+    seed: u64,
+    prices: Prices,
+}
+
+#[derive(Debug, Serialize)]
+struct Prices {
+    acceleration: u128,
+    banana: u128,
+    shell: u128,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Purchase {
+    Acceleration { amount: u32 },
+    Banana,
+    Shell { amount: u32 },
+}
+
+#[derive(Debug)]
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<std::io::Result<String>>,
+}
+
+impl ProcessRunner {
+    /// Creates a runner starting `program` with `args`.
+    pub fn new(program: PathBuf, args: Vec<String>) -> Self {
+        Self {
+            program,
+            args,
+            timeout: TURN_TIMEOUT,
+            process: None,
+        }
+    }
+
+    /// Sets how long the process has to answer each turn.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn play(&mut self, game: &mut Game) -> Result<(), String> {
+        if self.process.is_none() {
+            self.process = Some(Process::spawn(&self.program, &self.args)?);
+        }
+        let process = self.process.as_mut().unwrap();
+
+        let index = game.get_index();
+        let state = TurnState {
+            config: game.config(),
+            turns: game.turns(),
+            cars: game.cars(),
+            bananas: game.bananas(),
+            index,
+            seed: game.turn_seed(),
+            prices: Prices {
+                acceleration: game.get_accelerate_cost(1),
+                banana: game.get_banana_cost(),
+                shell: game.get_shell_cost(1),
+            },
+        };
+        let mut line = serde_json::to_string(&state).map_err(|e| e.to_string())?;
+        line.push('\n');
+
+        process
+            .stdin
+            .write_all(line.as_bytes())
+            .and_then(|()| process.stdin.flush())
+            .map_err(|e| format!("Failed to send the turn: {}", e))?;
+
+        let answer = match process.lines.recv_timeout(self.timeout) {
+            Ok(Ok(answer)) => answer,
+            Ok(Err(e)) => return Err(format!("Failed to read the answer: {}", e)),
+            Err(RecvTimeoutError::Timeout) => {
+                return Err(format!("No answer within {:?}", self.timeout))
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(match process.child.wait() {
+                    Ok(status) => format!("Process exited ({})", status),
+                    Err(e) => format!("Process exited: {}", e),
+                })
+            }
+        };
+
+        let purchases: Vec<Purchase> = serde_json::from_str(&answer)
+            .map_err(|e| format!("Invalid answer `{}`: {}", answer, e))?;
+
+        for purchase in purchases {
+            match purchase {
+                Purchase::Acceleration { amount } => game.buy_acceleration(amount),
+                Purchase::Banana => game.buy_banana(),
+                Purchase::Shell { amount } => game.buy_shell(amount),
+            };
+        }
+
+        Ok(())
+    }
+}
+
+impl Runner for ProcessRunner {
+    fn run(&mut self, game: &mut Game) -> Result<(), GameError> {
+        self.play(game).map_err(|e| {
+            self.process = None;
+            GameError::Script(e)
+        })
+    }
+
+    fn validate(&self) -> Result<(), GameError> {
+        Process::spawn(&self.program, &self.args)
+            .map(drop)
+            .map_err(GameError::Script)
+    }
+
+    fn box_clone(&self) -> Box<dyn Runner> {
+        Box::new(Self {
+            program: self.program.clone(),
+            args: self.args.clone(),
+            timeout: self.timeout,
+            process: None,
+        })
+    }
+}
+
+impl Process {
+    fn spawn(program: &PathBuf, args: &[String]) -> Result<Self, String> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to start {}: {}", program.display(), e))?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        // read on a separate thread so that a silent process can be timed out
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            child,
+            stdin,
+            lines,
+        })
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game_with(script: &str) -> (Game, ProcessRunner) {
+        let runner = ProcessRunner::new(
+            PathBuf::from("sh"),
+            vec!["-c".to_string(), script.to_string()],
+        )
+        .with_timeout(Duration::from_millis(200));
+
+        let mut game = Game::with_seed(RaceConfig::default(), 0);
+        for name in ["Alice", "Bob", "Charlie"] {
+            game.register(Car::new(runner.box_clone(), name.to_string()))
+                .unwrap();
+        }
+
+        (game, runner)
+    }
+
+    #[test]
+    fn buys_the_answered_purchases() {
+        let answer = r#"[{"type":"acceleration","amount":2},{"type":"banana"}]"#;
+        let (mut game, mut runner) =
+            game_with(&format!("while read line; do echo '{}'; done", answer));
+        let balance = game.cars()[0].balance;
+        let cost = game.get_accelerate_cost(2) + game.get_banana_cost();
+
+        runner.run(&mut game).unwrap();
+        assert_eq!(game.cars()[0].balance, balance - cost);
+
+        // the same process answers the next turn
+        runner.run(&mut game).unwrap();
+        assert!(game.cars()[0].balance < balance - cost);
+    }
+
+    #[test]
+    fn fails_on_misbehaving_processes() {
+        let (mut game, mut runner) = game_with("while read line; do echo nope; done");
+        assert!(runner
+            .run(&mut game)
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid answer"));
+
+        let (mut game, mut runner) = game_with("read line; exit 3");
+        assert!(runner
+            .run(&mut game)
+            .unwrap_err()
+            .to_string()
+            .contains("exited"));
+
+        let (mut game, mut runner) = game_with("sleep 5");
+        assert!(runner
+            .run(&mut game)
+            .unwrap_err()
+            .to_string()
+            .contains("No answer"));
+    }
+}
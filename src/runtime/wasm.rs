@@ -0,0 +1,426 @@
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+use wasmi::core::TrapCode;
+use wasmi::errors::ErrorKind;
+use wasmi::{
+    Caller, Config, Engine, Error, Instance, Linker, Module, Store, StoreLimits,
+    StoreLimitsBuilder, TypedFunc,
+};
+
+use crate::car::Car;
+use crate::error::GameError;
+use crate::game::Game;
+use crate::runtime::{Budget, Penalty, Runner};
+
+/// Module the host functions are imported from.
+const IMPORT_MODULE: &str = "portimao";
+
+/// Runs a WebAssembly module exporting a `take_your_turn` function taking and returning nothing.
+///
+/// The module is instantiated once, on the car's first turn, and kept for the whole race, so its
+/// memory and globals persist between turns. If it exports an `init` function, that is called
+/// right after instantiation.
+///
+/// The module can only import the functions of the `portimao` module, which read the race and buy
+/// for the car the same as a Lua script's `GameState`, see the README. Execution is metered with
+/// fuel, so a module always plays the same given the same race.
+pub struct WasmRunner {
+    engine: Engine,
+    module: Arc<Module>,
+    limits: WasmLimits,
+    vm: Option<Vm>,
+}
+
+/// The budgets a WebAssembly module runs under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WasmLimits {
+    /// Fuel a turn may consume, instantiating the module included.
+    pub fuel: u64,
+    /// Bytes of linear memory the module may have.
+    pub memory: usize,
+    /// What happens to a car that goes over either budget.
+    pub penalty: Penalty,
+}
+
+impl Default for WasmLimits {
+    fn default() -> Self {
+        Self {
+            fuel: 10_000_000,
+            memory: 16 * 1024 * 1024,
+            penalty: Penalty::SkipTurn,
+        }
+    }
+}
+
+/// An instantiated module.
+struct Vm {
+    store: Store<Host>,
+    take_your_turn: TypedFunc<(), ()>,
+}
+
+/// What the host functions see: the car's copy of the race while its turn is played.
+struct Host {
+    game: Game,
+    limits: StoreLimits,
+}
+
+impl WasmRunner {
+    /// Compiles the module, with the default limits.
+    pub fn new(wasm: &[u8]) -> Result<Self, GameError> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+
+        let module = Module::new(&engine, wasm)
+            .map_err(|e| GameError::Script(format!("Invalid WASM module: {}", e)))?;
+
+        Ok(Self {
+            engine,
+            module: Arc::new(module),
+            limits: WasmLimits::default(),
+            vm: None,
+        })
+    }
+
+    /// Sets the budgets the module runs under.
+    pub fn with_limits(mut self, limits: WasmLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Instantiates the module and calls its `init` function, if any.
+    fn load(&self, game: Game) -> Result<Vm, GameError> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.limits.memory)
+            .trap_on_grow_failure(true)
+            .build();
+        let mut store = Store::new(&self.engine, Host { game, limits });
+        store.limiter(|host| &mut host.limits);
+        store
+            .set_fuel(self.limits.fuel)
+            .expect("fuel metering is enabled");
+
+        let instance = self.check(
+            linker(&self.engine).and_then(|linker| {
+                linker
+                    .instantiate(&mut store, &self.module)?
+                    .start(&mut store)
+            }),
+            "Failed to instantiate WASM module",
+        )?;
+
+        let take_your_turn = exported(&instance, &store, "take_your_turn")?
+            .ok_or_else(|| GameError::Script("Missing take_your_turn function".to_string()))?;
+        if let Some(init) = exported(&instance, &store, "init")? {
+            self.check(init.call(&mut store, ()), "Error on calling init function")?;
+        }
+
+        Ok(Vm {
+            store,
+            take_your_turn,
+        })
+    }
+
+    /// Turns the outcome of running the module into the runner's result.
+    fn check<T>(&self, result: Result<T, Error>, context: &str) -> Result<T, GameError> {
+        result.map_err(|e| {
+            let penalty = self.limits.penalty;
+            match (e.kind(), e.as_trap_code()) {
+                (_, Some(TrapCode::OutOfFuel)) => GameError::BudgetExceeded {
+                    budget: Budget::Fuel(self.limits.fuel),
+                    penalty,
+                },
+                (ErrorKind::Memory(_), _) | (_, Some(TrapCode::GrowthOperationLimited)) => {
+                    GameError::BudgetExceeded {
+                        budget: Budget::Memory(self.limits.memory),
+                        penalty,
+                    }
+                }
+                _ => GameError::Script(format!("{}: {}", context, e)),
+            }
+        })
+    }
+}
+
+impl Runner for WasmRunner {
+    fn run(&mut self, game: &mut Game) -> Result<(), GameError> {
+        let mut vm = match self.vm.take() {
+            Some(mut vm) => {
+                vm.store.data_mut().game.clone_from(game);
+                vm
+            }
+            None => self.load(game.clone())?,
+        };
+
+        let result = self.check(
+            vm.take_your_turn.call(&mut vm.store, ()),
+            "Error on calling take_your_turn function",
+        );
+
+        vm.store
+            .set_fuel(self.limits.fuel)
+            .expect("fuel metering is enabled");
+        std::mem::swap(game, &mut vm.store.data_mut().game);
+        self.vm = Some(vm);
+
+        result
+    }
+
+    fn validate(&self) -> Result<(), GameError> {
+        self.load(Game::with_seed(Default::default(), 0)).map(drop)
+    }
+
+    /// The new runner instantiates the module again.
+    fn box_clone(&self) -> Box<dyn Runner> {
+        Box::new(Self {
+            engine: self.engine.clone(),
+            module: self.module.clone(),
+            limits: self.limits,
+            vm: None,
+        })
+    }
+}
+
+impl Debug for WasmRunner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WasmRunner")
+            .field("limits", &self.limits)
+            .field("instantiated", &self.vm.is_some())
+            .finish()
+    }
+}
+
+fn exported(
+    instance: &Instance,
+    store: &Store<Host>,
+    name: &str,
+) -> Result<Option<TypedFunc<(), ()>>, GameError> {
+    match instance.get_func(store, name) {
+        Some(func) => func
+            .typed(store)
+            .map(Some)
+            .map_err(|e| GameError::Script(format!("Invalid {} function: {}", name, e))),
+        None => Ok(None),
+    }
+}
+
+/// The race's `u128` amounts, which guests read as unsigned 64-bit integers.
+fn amount(value: u128) -> i64 {
+    u64::try_from(value).unwrap_or(u64::MAX) as i64
+}
+
+fn car<'a>(caller: &'a Caller<'_, Host>, index: i32) -> Result<&'a Car, Error> {
+    usize::try_from(index)
+        .ok()
+        .and_then(|index| caller.data().game.cars().get(index))
+        .ok_or_else(|| Error::new(format!("no car {}", index)))
+}
+
+/// The functions a module can import, all from the `portimao` module. Indexes start at 0 and
+/// `u32` values are passed as `i32`.
+fn linker(engine: &Engine) -> Result<Linker<Host>, Error> {
+    let mut linker = Linker::new(engine);
+
+    linker
+        .func_wrap(IMPORT_MODULE, "turns", |caller: Caller<'_, Host>| {
+            caller.data().game.turns() as i64
+        })?
+        .func_wrap(IMPORT_MODULE, "index", |caller: Caller<'_, Host>| {
+            caller.data().game.get_index() as i32
+        })?
+        .func_wrap(IMPORT_MODULE, "seed", |caller: Caller<'_, Host>| {
+            caller.data().game.turn_seed() as i64
+        })?
+        .func_wrap(
+            IMPORT_MODULE,
+            "finish_distance",
+            |caller: Caller<'_, Host>| caller.data().game.config().finish_distance as i32,
+        )?
+        .func_wrap(IMPORT_MODULE, "max_turns", |caller: Caller<'_, Host>| {
+            caller.data().game.config().max_turns as i64
+        })?
+        .func_wrap(IMPORT_MODULE, "car_count", |caller: Caller<'_, Host>| {
+            caller.data().game.cars().len() as i32
+        })?
+        .func_wrap(
+            IMPORT_MODULE,
+            "car_balance",
+            |caller: Caller<'_, Host>, index: i32| {
+                car(&caller, index).map(|car| amount(car.balance))
+            },
+        )?
+        .func_wrap(
+            IMPORT_MODULE,
+            "car_speed",
+            |caller: Caller<'_, Host>, index: i32| car(&caller, index).map(|car| car.speed as i32),
+        )?
+        .func_wrap(
+            IMPORT_MODULE,
+            "car_y",
+            |caller: Caller<'_, Host>, index: i32| car(&caller, index).map(|car| car.y as i32),
+        )?
+        .func_wrap(IMPORT_MODULE, "banana_count", |caller: Caller<'_, Host>| {
+            caller.data().game.bananas().len() as i32
+        })?
+        .func_wrap(
+            IMPORT_MODULE,
+            "banana",
+            |caller: Caller<'_, Host>, index: i32| {
+                usize::try_from(index)
+                    .ok()
+                    .and_then(|index| caller.data().game.bananas().get(index))
+                    .map(|&y| y as i32)
+                    .ok_or_else(|| Error::new(format!("no banana {}", index)))
+            },
+        )?
+        .func_wrap(
+            IMPORT_MODULE,
+            "accelerate_cost",
+            |caller: Caller<'_, Host>, amount_: i32| {
+                amount(caller.data().game.get_accelerate_cost(amount_ as u32))
+            },
+        )?
+        .func_wrap(IMPORT_MODULE, "banana_cost", |caller: Caller<'_, Host>| {
+            amount(caller.data().game.get_banana_cost())
+        })?
+        .func_wrap(
+            IMPORT_MODULE,
+            "shell_cost",
+            |caller: Caller<'_, Host>, amount_: i32| {
+                amount(caller.data().game.get_shell_cost(amount_ as u32))
+            },
+        )?
+        .func_wrap(
+            IMPORT_MODULE,
+            "buy_acceleration",
+            |mut caller: Caller<'_, Host>, amount: i32| {
+                caller.data_mut().game.buy_acceleration(amount as u32) as i32
+            },
+        )?
+        .func_wrap(
+            IMPORT_MODULE,
+            "buy_banana",
+            |mut caller: Caller<'_, Host>| caller.data_mut().game.buy_banana() as i32,
+        )?
+        .func_wrap(
+            IMPORT_MODULE,
+            "buy_shell",
+            |mut caller: Caller<'_, Host>, amount: i32| {
+                caller.data_mut().game.buy_shell(amount as u32) as i32
+            },
+        )?;
+
+    Ok(linker)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RaceConfig;
+    use crate::runtime::tests::{persists_between_turns, race};
+
+    fn runner(wat: &str) -> Result<WasmRunner, GameError> {
+        let limits = WasmLimits {
+            fuel: 100_000,
+            memory: 1024 * 1024,
+            penalty: Penalty::Disqualify,
+        };
+        Ok(WasmRunner::new(&wat::parse_str(wat).unwrap())?.with_limits(limits))
+    }
+
+    fn run(wat: &str) -> Result<Game, GameError> {
+        let mut runner = runner(wat)?;
+        let mut game = race(RaceConfig::default(), &runner);
+
+        runner.run(&mut game)?;
+        Ok(game)
+    }
+
+    /// Buys as many accelerations as the number of turns it played.
+    const RAMP: &str = r#"
+        (module
+            (import "portimao" "buy_acceleration" (func $buy_acceleration (param i32) (result i32)))
+            (global $turns (mut i32) (i32.const 0))
+            (global $inits (mut i32) (i32.const 0))
+            (func (export "init")
+                (global.set $inits (i32.add (global.get $inits) (i32.const 1))))
+            (func (export "take_your_turn")
+                (if (i32.ne (global.get $inits) (i32.const 1)) (then unreachable))
+                (global.set $turns (i32.add (global.get $turns) (i32.const 1)))
+                (drop (call $buy_acceleration (global.get $turns)))))
+    "#;
+
+    #[test]
+    fn state_persists_between_turns() {
+        // a clone starts over with a new instance, which calls `init` again
+        persists_between_turns(Box::new(runner(RAMP).unwrap()));
+    }
+
+    #[test]
+    fn reads_the_race() {
+        let game = run(r#"
+            (module
+                (import "portimao" "car_balance" (func $car_balance (param i32) (result i64)))
+                (import "portimao" "accelerate_cost" (func $accelerate_cost (param i32) (result i64)))
+                (import "portimao" "index" (func $index (result i32)))
+                (import "portimao" "buy_shell" (func $buy_shell (param i32) (result i32)))
+                (func (export "take_your_turn")
+                    ;; buy a shell if the balance and the price read right
+                    (if (i64.eq (call $car_balance (call $index)) (i64.const 17500))
+                        (then (if (i64.eq (call $accelerate_cost (i32.const 1)) (i64.const 12))
+                            (then (drop (call $buy_shell (i32.const 1)))))))))
+        "#)
+        .unwrap();
+        assert!(game.cars()[0].balance < 17500);
+
+        let error = run(r#"
+            (module
+                (import "portimao" "car_y" (func $car_y (param i32) (result i32)))
+                (func (export "take_your_turn") (drop (call $car_y (i32.const 5)))))
+        "#)
+        .unwrap_err();
+        assert!(error.to_string().contains("no car 5"), "{}", error);
+    }
+
+    #[test]
+    fn sandbox() {
+        assert!(matches!(
+            runner(r#"(module (import "wasi_snapshot_preview1" "fd_write" (func (param i32 i32 i32 i32) (result i32))) (func (export "take_your_turn")))"#)
+                .unwrap()
+                .validate(),
+            Err(GameError::Script(_))
+        ));
+        assert!(matches!(
+            runner("(module)").unwrap().validate(),
+            Err(GameError::Script(_))
+        ));
+        assert!(runner("(module (func (export \"take_your_turn\")))")
+            .unwrap()
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn limits() {
+        assert_eq!(
+            run(r#"(module (func (export "take_your_turn") (loop (br 0))))"#).unwrap_err(),
+            GameError::BudgetExceeded {
+                budget: Budget::Fuel(100_000),
+                penalty: Penalty::Disqualify,
+            }
+        );
+        assert_eq!(
+            run(r#"
+                (module
+                    (memory 1)
+                    (func (export "take_your_turn") (drop (memory.grow (i32.const 100)))))
+            "#)
+            .unwrap_err(),
+            GameError::BudgetExceeded {
+                budget: Budget::Memory(1024 * 1024),
+                penalty: Penalty::Disqualify,
+            }
+        );
+    }
+}
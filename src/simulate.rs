@@ -0,0 +1,112 @@
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+
+use crate::car::Car;
+use crate::config::RaceConfig;
+use crate::error::GameError;
+use crate::game::Game;
+
+/// Races, as they finish, of [`simulate_many`].
+#[derive(Debug)]
+pub struct Simulations {
+    results: Receiver<(u64, Result<Game, GameError>)>,
+}
+
+/// Races `cars`, seated in order, once per seed, on as many threads as there are cores.
+///
+/// Every race gets fresh copies of the cars, with runners of their own, so no two races share a
+/// Lua. The finished races, with their seed, come in the order they finish, which is not the
+/// order of the seeds. Dropping the iterator stops the races that haven't started.
+pub fn simulate_many(
+    config: &RaceConfig,
+    cars: &[Car],
+    seeds: impl IntoIterator<Item = u64>,
+) -> Simulations {
+    let seeds: Arc<[u64]> = seeds.into_iter().collect();
+    let next = Arc::new(AtomicUsize::new(0));
+    let threads = thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(seeds.len());
+
+    let (sender, results) = mpsc::channel();
+    for _ in 0..threads {
+        let config = config.clone();
+        let cars = cars.to_vec();
+        let seeds = seeds.clone();
+        let next = next.clone();
+        let sender = sender.clone();
+
+        thread::spawn(move || {
+            while let Some(&seed) = seeds.get(next.fetch_add(1, Ordering::Relaxed)) {
+                if sender.send((seed, race(&config, &cars, seed))).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    Simulations { results }
+}
+
+fn race(config: &RaceConfig, cars: &[Car], seed: u64) -> Result<Game, GameError> {
+    let mut game = Game::with_seed(config.clone(), seed);
+    for car in cars {
+        game.register(car.clone())?;
+    }
+    game.start()?;
+    game.race()?;
+
+    Ok(game)
+}
+
+impl Iterator for Simulations {
+    type Item = (u64, Result<Game, GameError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.results.recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::LuaRunner;
+
+    #[test]
+    fn races_every_seed_like_a_single_race() {
+        let script = r#"
+            function takeYourTurn()
+                GameState:buy_acceleration(math.random(0, 2))
+                if math.random() < 0.1 then GameState:buy_shell(1) end
+            end
+        "#;
+        let cars: Vec<Car> = ["Alice", "Bob", "Charlie"]
+            .into_iter()
+            .map(|name| {
+                let runner = LuaRunner::new(script.to_string());
+                Car::new(Box::new(runner), name.to_string())
+            })
+            .collect();
+        let config = RaceConfig::default();
+
+        let mut results: Vec<(u64, Result<Game, GameError>)> =
+            simulate_many(&config, &cars, 0..20).collect();
+        results.sort_by_key(|(seed, _)| *seed);
+
+        assert_eq!(results.len(), 20);
+        for (seed, game) in results {
+            let alone = race(&config, &cars, seed).unwrap();
+            assert_eq!(game.unwrap().log_json(), alone.log_json());
+        }
+    }
+
+    #[test]
+    fn reports_races_that_cannot_start() {
+        let results: Vec<_> = simulate_many(&RaceConfig::default(), &[], [1, 2]).collect();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|(_, game)| game.is_err()));
+    }
+}
@@ -0,0 +1,564 @@
+use std::io::{self, Write};
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, Event as TermEvent, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::action::Action;
+use crate::car::Car;
+use crate::log::{Event, Header, Log, Outcome, RaceLog, Record};
+use crate::runtime::Penalty;
+
+/// Colors the cars are drawn in, by seat.
+const COLORS: [Color; 6] = [
+    Color::Cyan,
+    Color::Magenta,
+    Color::Green,
+    Color::Blue,
+    Color::Red,
+    Color::LightYellow,
+];
+/// Frames skipped by page up and page down.
+const PAGE: isize = 10;
+
+/// A race as a spectator follows it, from a saved log or from records coming in as it is played.
+///
+/// Frame 0 is the race before its first turn, frame `n` the race after `n` turns, and the last
+/// frame, once the result is in, the race as it ended. Playing moves one frame per `delay`.
+#[derive(Debug, Clone)]
+pub struct Spectator {
+    header: Option<Header>,
+    turns: Vec<Log>,
+    result: Option<Outcome>,
+    frame: usize,
+    paused: bool,
+    delay: Duration,
+}
+
+/// A frame's race.
+struct Snapshot<'a> {
+    cars: &'a [Car],
+    bananas: &'a [u32],
+    costs: Option<&'a [Option<u128>]>,
+}
+
+impl Default for Spectator {
+    fn default() -> Self {
+        Self {
+            header: None,
+            turns: Vec::new(),
+            result: None,
+            frame: 0,
+            paused: false,
+            delay: Duration::from_millis(200),
+        }
+    }
+}
+
+impl Spectator {
+    /// Follows a saved race from its start.
+    pub fn from_log(log: RaceLog) -> Self {
+        Self {
+            header: Some(log.header),
+            turns: log.turns,
+            result: Some(log.result),
+            ..Self::default()
+        }
+    }
+
+    /// Adds the next record of the race.
+    pub fn push(&mut self, record: Record) {
+        match record {
+            Record::Header(header) => self.header = Some(header),
+            Record::Turn(turn) => self.turns.push(turn),
+            Record::Result(result) => self.result = Some(result),
+        }
+    }
+
+    /// How many frames there are so far.
+    pub fn frames(&self) -> usize {
+        self.turns.len() + usize::from(self.result.is_some())
+    }
+
+    /// The frame shown.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Moves `by` frames, staying within the frames there are.
+    pub fn step(&mut self, by: isize) {
+        let frame = self.frame.saturating_add_signed(by);
+        self.seek(frame);
+    }
+
+    /// Shows `frame`, or the last frame if there aren't that many.
+    pub fn seek(&mut self, frame: usize) {
+        self.frame = frame.min(self.frames().saturating_sub(1));
+    }
+
+    /// Whether playing is paused.
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Moves to the next frame, unless paused or at the last frame.
+    pub fn tick(&mut self) {
+        if !self.paused {
+            self.step(1);
+        }
+    }
+
+    /// Acts on a key press, returning `false` when the spectator quits.
+    pub fn handle(&mut self, key: KeyCode) -> bool {
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char(' ') => self.paused = !self.paused,
+            KeyCode::Right | KeyCode::Char('l') => self.pause_and_step(1),
+            KeyCode::Left | KeyCode::Char('h') => self.pause_and_step(-1),
+            KeyCode::PageDown => self.step(PAGE),
+            KeyCode::PageUp => self.step(-PAGE),
+            KeyCode::Home | KeyCode::Char('g') => self.seek(0),
+            KeyCode::End | KeyCode::Char('G') => self.seek(usize::MAX),
+            KeyCode::Char('+') => self.delay = (self.delay / 2).max(Duration::from_millis(25)),
+            KeyCode::Char('-') => self.delay = (self.delay * 2).min(Duration::from_secs(2)),
+            _ => {}
+        }
+        true
+    }
+
+    fn pause_and_step(&mut self, by: isize) {
+        self.paused = true;
+        self.step(by);
+    }
+
+    fn snapshot(&self) -> Option<Snapshot<'_>> {
+        match (self.turns.get(self.frame), &self.result) {
+            (Some(turn), _) => Some(Snapshot {
+                cars: &turn.cars,
+                bananas: &turn.bananas,
+                costs: Some(&turn.costs),
+            }),
+            (None, Some(result)) => Some(Snapshot {
+                cars: &result.cars,
+                bananas: &result.bananas,
+                costs: None,
+            }),
+            (None, None) => None,
+        }
+    }
+
+    /// Draws the frame shown over the whole of `frame`.
+    pub fn render(&self, frame: &mut Frame) {
+        let Some(snapshot) = self.snapshot() else {
+            frame.render_widget(
+                Paragraph::new("Waiting for the race to start..."),
+                frame.area(),
+            );
+            return;
+        };
+        let cars = snapshot.cars.len() as u16;
+        let [title, track, table, prices, feed, help] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(cars + 3),
+            Constraint::Length(cars + 3),
+            Constraint::Length(1),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        frame.render_widget(self.title(), title);
+        frame.render_widget(self.track(&snapshot, track), track);
+        frame.render_widget(self.table(&snapshot), table);
+        frame.render_widget(prices_line(snapshot.costs), prices);
+        frame.render_widget(self.feed(), feed);
+        frame.render_widget(
+            Line::from(
+                "space pause · ←/→ step · PgUp/PgDn ±10 · Home/End first/last · +/- speed · q quit",
+            )
+            .dark_gray(),
+            help,
+        );
+    }
+
+    fn title(&self) -> Line<'_> {
+        let seed = self
+            .header
+            .as_ref()
+            .map_or(String::new(), |header| format!(" · seed {}", header.seed));
+        let at = match self.turns.get(self.frame) {
+            Some(turn) => format!("turn {}", turn.turn),
+            None => "finished".to_string(),
+        };
+        let status = if self.paused { "paused" } else { "playing" };
+        Line::from(vec![
+            Span::from("portimao").bold(),
+            Span::from(format!(
+                "{} · {} · frame {}/{} · {}",
+                seed,
+                at,
+                self.frame + 1,
+                self.frames(),
+                status
+            )),
+        ])
+    }
+
+    fn finish_distance(&self) -> u32 {
+        self.header
+            .as_ref()
+            .map_or(1000, |header| header.config.finish_distance)
+            .max(1)
+    }
+
+    /// A lane per car from the start to the finish line, with the bananas on every lane.
+    fn track(&self, snapshot: &Snapshot, area: Rect) -> Paragraph<'_> {
+        let finish = self.finish_distance();
+        let names = 12;
+        let width = area.width.saturating_sub(names + 3).max(2) as u32;
+        let column = |y: u32| (y.min(finish) as u64 * (width - 1) as u64 / finish as u64) as usize;
+
+        let mut lanes = Vec::new();
+        for (index, car) in snapshot.cars.iter().enumerate() {
+            let mut cells: Vec<Span> = (0..width).map(|_| Span::from("·").dark_gray()).collect();
+            for &banana in snapshot.bananas {
+                cells[column(banana)] = Span::from("b").yellow().bold();
+            }
+            let marker = if car.disqualified { "x" } else { "●" };
+            cells[column(car.y)] = Span::styled(marker, car_style(index));
+
+            let mut lane = vec![Span::styled(
+                format!(
+                    "{:<width$} ",
+                    truncate(&car.name, names as usize),
+                    width = names as usize
+                ),
+                car_style(index),
+            )];
+            lane.extend(cells);
+            lane.push(Span::from("|").bold());
+            lanes.push(Line::from(lane));
+        }
+
+        Paragraph::new(lanes).block(Block::bordered().title(format!("Track, finish at {}", finish)))
+    }
+
+    fn table(&self, snapshot: &Snapshot) -> Table<'_> {
+        let bought = self
+            .frame
+            .checked_sub(1)
+            .and_then(|turn| self.turns.get(turn));
+        let winner = self.result.as_ref().and_then(|result| result.winner);
+
+        let rows = snapshot.cars.iter().enumerate().map(|(index, car)| {
+            let status = if car.disqualified {
+                "disqualified"
+            } else if self.frame + 1 == self.frames() && winner == Some(index) {
+                "winner"
+            } else {
+                ""
+            };
+            let last_turn = bought.map_or(String::new(), |turn| purchases(turn, index));
+            Row::new(vec![
+                car.name.clone(),
+                car.y.to_string(),
+                car.speed.to_string(),
+                car.balance.to_string(),
+                last_turn,
+                status.to_string(),
+            ])
+            .style(car_style(index))
+        });
+
+        Table::new(
+            rows,
+            [
+                Constraint::Length(12),
+                Constraint::Length(6),
+                Constraint::Length(6),
+                Constraint::Length(10),
+                Constraint::Min(20),
+                Constraint::Length(12),
+            ],
+        )
+        .header(
+            Row::new(["car", "y", "speed", "balance", "last turn", ""])
+                .add_modifier(Modifier::BOLD),
+        )
+        .block(Block::bordered())
+    }
+
+    /// What happened in the turns up to the frame shown, latest first.
+    fn feed(&self) -> List<'_> {
+        let names: Vec<&str> = match (&self.header, self.turns.first()) {
+            (Some(header), _) => header.cars.iter().map(String::as_str).collect(),
+            (None, Some(turn)) => turn.cars.iter().map(|car| car.name.as_str()).collect(),
+            (None, None) => Vec::new(),
+        };
+        let items: Vec<ListItem> = self.turns[..self.frame.min(self.turns.len())]
+            .iter()
+            .rev()
+            .flat_map(|turn| turn.events.iter().rev())
+            .map(|event| ListItem::new(describe(event, &names)))
+            .collect();
+
+        List::new(items).block(Block::bordered().title("Events"))
+    }
+}
+
+fn car_style(index: usize) -> Style {
+    Style::default().fg(COLORS[index % COLORS.len()])
+}
+
+fn truncate(name: &str, width: usize) -> String {
+    name.chars().take(width).collect()
+}
+
+fn prices_line(costs: Option<&[Option<u128>]>) -> Line<'static> {
+    let price =
+        |cost: &Option<u128>| cost.map_or("unaffordable".to_string(), |cost| cost.to_string());
+    match costs {
+        Some([acceleration, banana, shell, ..]) => Line::from(format!(
+            "prices: acceleration {} · banana {} · shell {}",
+            price(acceleration),
+            price(banana),
+            price(shell)
+        )),
+        _ => Line::from("the race is over"),
+    }
+}
+
+/// What the car bought during `turn`, and what it paid.
+fn purchases(turn: &Log, car: usize) -> String {
+    let mut items = Vec::new();
+    let mut cost = 0;
+    for purchase in turn.purchases.iter().filter(|p| p.action.car() == car) {
+        items.push(match purchase.action {
+            Action::Acceleration { amount, .. } => format!("+{} speed", amount),
+            Action::Banana { .. } => "banana".to_string(),
+            Action::Shell { amount, .. } => format!("{} shell", amount),
+        });
+        cost += purchase.cost;
+    }
+
+    if items.is_empty() {
+        String::new()
+    } else {
+        format!("{} for {}", items.join(", "), cost)
+    }
+}
+
+fn describe(event: &Event, names: &[&str]) -> String {
+    let name = |car: usize| names.get(car).copied().unwrap_or("?");
+    match event {
+        Event::BudgetExceeded {
+            turn,
+            car,
+            budget,
+            penalty,
+        } => {
+            let penalty = match penalty {
+                Penalty::SkipTurn => "lost its turn",
+                Penalty::Disqualify => "is disqualified",
+            };
+            format!(
+                "turn {}: {} went over {} and {}",
+                turn,
+                name(*car),
+                budget,
+                penalty
+            )
+        }
+        Event::ScriptError { turn, car, message } => {
+            format!("turn {}: {} failed: {}", turn, name(*car), message)
+        }
+        Event::Collision { turn, car, banana } => {
+            format!(
+                "turn {}: {} ran into the banana at {}",
+                turn,
+                name(*car),
+                banana
+            )
+        }
+        Event::ShellHit { turn, car, target } => {
+            format!(
+                "turn {}: {}'s shell hit {}",
+                turn,
+                name(*car),
+                name(*target)
+            )
+        }
+        Event::ShellIntercepted { turn, car, banana } => format!(
+            "turn {}: {}'s shell hit the banana at {}",
+            turn,
+            name(*car),
+            banana
+        ),
+    }
+}
+
+/// Shows the race `records` come from in the terminal until the spectator quits. Records can
+/// keep coming while it plays, from a race being played.
+pub fn watch(records: Receiver<Record>) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, records);
+    ratatui::restore();
+    result
+}
+
+fn run(terminal: &mut DefaultTerminal, records: Receiver<Record>) -> io::Result<()> {
+    let mut spectator = Spectator::default();
+    let mut last_tick = Instant::now();
+    loop {
+        while let Ok(record) = records.try_recv() {
+            spectator.push(record);
+        }
+        terminal.draw(|frame| spectator.render(frame))?;
+
+        let timeout = spectator.delay.saturating_sub(last_tick.elapsed());
+        if event::poll(timeout)? {
+            if let TermEvent::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !spectator.handle(key.code) {
+                    return Ok(());
+                }
+            }
+        }
+        if last_tick.elapsed() >= spectator.delay {
+            spectator.tick();
+            last_tick = Instant::now();
+        }
+    }
+}
+
+/// A log sink, for [`Game::with_log_sink`](crate::Game::with_log_sink), that hands the race's
+/// records to a spectator as they are streamed.
+///
+/// Records stop going anywhere once the spectator quits, without stopping the race.
+#[derive(Debug)]
+pub struct Feed {
+    records: Sender<Record>,
+    line: Vec<u8>,
+}
+
+impl Feed {
+    /// Creates a sink sending the records to `records`.
+    pub fn new(records: Sender<Record>) -> Self {
+        Self {
+            records,
+            line: Vec::new(),
+        }
+    }
+}
+
+impl Write for Feed {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            if byte != b'\n' {
+                self.line.push(byte);
+                continue;
+            }
+
+            let record = serde_json::from_slice(&self.line)?;
+            self.line.clear();
+            let _ = self.records.send(record);
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    use super::*;
+    use crate::config::RaceConfig;
+    use crate::game::Game;
+    use crate::runtime::LuaRunner;
+
+    fn race() -> Game {
+        let script = r#"
+            function takeYourTurn()
+                GameState:buy_acceleration(2)
+                if math.random() < 0.3 then GameState:buy_banana() end
+                if math.random() < 0.3 then GameState:buy_shell(1) end
+            end
+        "#;
+        let mut game = Game::with_seed(RaceConfig::default(), 1);
+        for name in ["Alice", "Bob"] {
+            let runner = LuaRunner::new(script.to_string());
+            game.register(Car::new(Box::new(runner), name.to_string()))
+                .unwrap();
+        }
+        game.start().unwrap();
+        game
+    }
+
+    #[test]
+    fn steps_and_seeks_within_the_frames() {
+        let mut game = race();
+        game.race().unwrap();
+        let mut spectator = Spectator::from_log(game.race_log());
+        let last = game.logs().len();
+        assert_eq!(spectator.frames(), last + 1);
+
+        spectator.step(-1);
+        assert_eq!(spectator.frame(), 0);
+        spectator.tick();
+        assert_eq!(spectator.frame(), 1);
+
+        spectator.handle(KeyCode::Char(' '));
+        spectator.tick();
+        assert!(spectator.paused() && spectator.frame() == 1);
+
+        spectator.handle(KeyCode::End);
+        assert_eq!(spectator.frame(), last);
+        spectator.handle(KeyCode::Right);
+        assert_eq!(spectator.frame(), last);
+        spectator.handle(KeyCode::PageUp);
+        assert_eq!(spectator.frame(), last - 10);
+        assert!(!spectator.handle(KeyCode::Char('q')));
+    }
+
+    #[test]
+    fn follows_a_race_being_played() {
+        let (sender, records) = mpsc::channel();
+        let mut game = race().with_log_sink(Feed::new(sender));
+        game.race().unwrap();
+
+        let mut spectator = Spectator::default();
+        for record in records.try_iter() {
+            spectator.push(record);
+        }
+        assert_eq!(spectator.frames(), game.logs().len() + 1);
+
+        spectator.seek(usize::MAX);
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|frame| spectator.render(frame)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+
+        let winner = game.winner().unwrap();
+        assert!(screen.contains("finished"));
+        assert!(screen.contains(&winner.name));
+        assert!(screen.contains("winner"));
+        assert!(screen.contains(&winner.y.to_string()));
+    }
+}
@@ -0,0 +1,261 @@
+use std::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+
+use crate::car::Car;
+use crate::config::RaceConfig;
+use crate::error::GameError;
+use crate::game::Game;
+
+/// Races a pool of cars against each other in every seating and with every seed, so that a car's
+/// results don't come down to its seat or to one race's luck.
+///
+/// A seating is an ordered choice of `seats` cars of the pool, registered in that order, so with
+/// `n` cars there are `n! / (n - seats)!` seatings, each raced once per seed.
+#[derive(Debug, Clone)]
+pub struct Tournament {
+    config: RaceConfig,
+    cars: Vec<Car>,
+    seats: usize,
+    seeds: Vec<u64>,
+}
+
+/// How each car of a tournament did.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TournamentReport {
+    /// Number of races run.
+    pub races: usize,
+    /// The cars, in pool order.
+    pub standings: Vec<Standing>,
+}
+
+/// How a car did over a tournament's races.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Standing {
+    /// The car's name.
+    pub name: String,
+    /// Races the car was seated in.
+    pub races: usize,
+    /// Races the car won.
+    pub wins: usize,
+    /// Average finishing position, 1 being the winner.
+    pub average_position: f64,
+    /// Average balance left at the end of a race.
+    pub average_balance: f64,
+}
+
+impl Tournament {
+    /// Creates a tournament seating as many cars of the pool as a race takes, with seed 0.
+    pub fn new(config: RaceConfig, cars: Vec<Car>) -> Self {
+        let seats = cars.len().min(config.max_players);
+        Self {
+            config,
+            cars,
+            seats,
+            seeds: vec![0],
+        }
+    }
+
+    /// Sets how many cars race at once.
+    pub fn with_seats(mut self, seats: usize) -> Self {
+        self.seats = seats;
+        self
+    }
+
+    /// Sets the seeds every seating is raced with.
+    pub fn with_seeds(mut self, seeds: Vec<u64>) -> Self {
+        self.seeds = seeds;
+        self
+    }
+
+    /// Every seating, as indexes in the pool in seat order.
+    pub fn seatings(&self) -> Vec<Vec<usize>> {
+        let mut seatings = Vec::new();
+        let mut seating = Vec::with_capacity(self.seats);
+        permutations(self.cars.len(), self.seats, &mut seating, &mut seatings);
+        seatings
+    }
+
+    /// Runs every race.
+    pub fn run(&self) -> Result<TournamentReport, GameError> {
+        if self.seats > self.cars.len() {
+            return Err(GameError::PlayerCount {
+                min: self.seats,
+                max: self.seats,
+                registered: self.cars.len(),
+            });
+        }
+
+        let mut totals = vec![Totals::default(); self.cars.len()];
+        let mut races = 0;
+        for seating in self.seatings() {
+            for &seed in &self.seeds {
+                let mut game = Game::with_seed(self.config.clone(), seed);
+                for &car in &seating {
+                    game.register(self.cars[car].clone())?;
+                }
+                game.start()?;
+                game.race()?;
+
+                for (position, seat) in game.standings().into_iter().enumerate() {
+                    let totals = &mut totals[seating[seat]];
+                    totals.races += 1;
+                    totals.wins += usize::from(position == 0 && game.winner().is_some());
+                    totals.positions += position + 1;
+                    totals.balances += game.cars()[seat].balance;
+                }
+                races += 1;
+            }
+        }
+
+        let standings = self
+            .cars
+            .iter()
+            .zip(totals)
+            .map(|(car, totals)| Standing {
+                name: car.name.clone(),
+                races: totals.races,
+                wins: totals.wins,
+                average_position: average(totals.positions as f64, totals.races),
+                average_balance: average(totals.balances as f64, totals.races),
+            })
+            .collect();
+
+        Ok(TournamentReport { races, standings })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Totals {
+    races: usize,
+    wins: usize,
+    positions: usize,
+    balances: u128,
+}
+
+fn average(total: f64, races: usize) -> f64 {
+    if races == 0 {
+        0.0
+    } else {
+        total / races as f64
+    }
+}
+
+/// Pushes every ordered choice of `seats` numbers below `pool` that starts with `seating`.
+fn permutations(pool: usize, seats: usize, seating: &mut Vec<usize>, into: &mut Vec<Vec<usize>>) {
+    if seating.len() == seats {
+        into.push(seating.clone());
+        return;
+    }
+
+    for car in 0..pool {
+        if !seating.contains(&car) {
+            seating.push(car);
+            permutations(pool, seats, seating, into);
+            seating.pop();
+        }
+    }
+}
+
+impl Display for TournamentReport {
+    /// A table of the cars, best first.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut standings: Vec<&Standing> = self.standings.iter().collect();
+        standings.sort_by(|a, b| {
+            b.wins
+                .cmp(&a.wins)
+                .then(a.average_position.total_cmp(&b.average_position))
+        });
+
+        writeln!(f, "{} races", self.races)?;
+        writeln!(
+            f,
+            "{:<16} {:>6} {:>6} {:>9} {:>12}",
+            "car", "races", "wins", "position", "balance"
+        )?;
+        for standing in standings {
+            writeln!(
+                f,
+                "{:<16} {:>6} {:>6} {:>9.2} {:>12.1}",
+                standing.name,
+                standing.races,
+                standing.wins,
+                standing.average_position,
+                standing.average_balance
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::LuaRunner;
+
+    fn car(name: &str, speed: u32) -> Car {
+        let script = format!(
+            "function takeYourTurn() GameState:buy_acceleration({}) end",
+            speed
+        );
+        Car::new(Box::new(LuaRunner::new(script)), name.to_string())
+    }
+
+    #[test]
+    fn seatings() {
+        let tournament = Tournament::new(
+            RaceConfig::default(),
+            vec![car("a", 1), car("b", 1), car("c", 1)],
+        );
+        assert_eq!(tournament.seatings().len(), 6);
+
+        let seatings = tournament.with_seats(2).seatings();
+        assert_eq!(seatings.len(), 6);
+        assert!(seatings.contains(&vec![2, 0]));
+    }
+
+    #[test]
+    fn aggregates_every_race() {
+        let report = Tournament::new(
+            RaceConfig::default(),
+            vec![car("slow", 1), car("fast", 3), car("idle", 0)],
+        )
+        .with_seats(2)
+        .with_seeds(vec![1, 2])
+        .run()
+        .unwrap();
+
+        assert_eq!(report.races, 12);
+        let [slow, fast, idle] = &report.standings[..] else {
+            panic!("{:?}", report.standings);
+        };
+        assert_eq!((slow.races, slow.wins), (8, 4));
+        assert_eq!((fast.races, fast.wins, fast.average_position), (8, 8, 1.0));
+        assert_eq!((idle.races, idle.wins, idle.average_position), (8, 0, 2.0));
+        assert_eq!(
+            idle.average_balance,
+            RaceConfig::default().starting_balance as f64
+        );
+    }
+
+    #[test]
+    fn idle_cars_draw() {
+        let config = RaceConfig {
+            max_turns: 20,
+            ..RaceConfig::default()
+        };
+        let report = Tournament::new(config, vec![car("a", 0), car("b", 0)])
+            .run()
+            .unwrap();
+
+        assert_eq!(report.races, 2);
+        assert!(report.standings.iter().all(|standing| standing.wins == 0));
+    }
+
+    #[test]
+    fn needs_enough_cars() {
+        let tournament = Tournament::new(RaceConfig::default(), vec![car("a", 1), car("b", 1)]);
+        assert!(tournament.with_seats(3).run().is_err());
+    }
+}
@@ -0,0 +1,253 @@
+//! Numbers with 18 decimals, stored as integers counting `1 / WAD`ths, the way prices are
+//! computed on chain.
+//!
+//! Every operation rounds its result toward zero, and returns `None` when it doesn't fit. The
+//! logarithm and the exponential work with 36 decimals on the way, and are within `2e-18` of the
+//! exact value, relative to it for exponentials above 1.
+
+/// One.
+pub const WAD: i128 = 1_000_000_000_000_000_000;
+
+/// One with 36 decimals, that `ln` and `exp` work with on the way to keep all 18.
+const ONE_36: i128 = WAD * WAD;
+
+/// ln 2 with 36 decimals.
+const LN_2: i128 = 693_147_180_559_945_309_417_232_121_458_176_568;
+
+/// `x` rounded to the nearest 18th decimal, or None when it isn't finite or doesn't fit.
+pub fn from_f64(x: f64) -> Option<i128> {
+    let scaled = (x * WAD as f64).round();
+    (scaled.is_finite() && scaled.abs() < i128::MAX as f64).then_some(scaled as i128)
+}
+
+/// The integer `n`, or None when it doesn't fit.
+pub fn from_int(n: u128) -> Option<i128> {
+    i128::try_from(n).ok()?.checked_mul(WAD)
+}
+
+/// `a * b`.
+pub fn mul(a: i128, b: i128) -> Option<i128> {
+    signed_mul_div(a, b, WAD)
+}
+
+/// `a / b`, or None when `b` is zero.
+pub fn div(a: i128, b: i128) -> Option<i128> {
+    signed_mul_div(a, WAD, b)
+}
+
+/// Natural logarithm of `x`, or None unless `x` is positive.
+pub fn ln(x: i128) -> Option<i128> {
+    if x <= 0 {
+        return None;
+    }
+
+    // x = m * 2^k with m in [1, 2), with 36 decimals
+    let mut k = WAD.leading_zeros() as i32 - x.leading_zeros() as i32;
+    let scale = |k: i32| {
+        if k >= 0 {
+            signed_mul_div(x, WAD, 1 << k)
+        } else {
+            signed_mul_div(x, WAD << -k, 1)
+        }
+    };
+    let mut m = scale(k)?;
+    if m < ONE_36 {
+        k -= 1;
+        m = scale(k)?;
+    }
+
+    // ln m = 2 atanh z = 2 (z + z^3 / 3 + z^5 / 5 + ...) with z = (m - 1) / (m + 1) below 1/3
+    let z = signed_mul_div(m - ONE_36, ONE_36, m + ONE_36)?;
+    let z_squared = signed_mul_div(z, z, ONE_36)?;
+    let (mut power, mut sum, mut n) = (z, z, 1);
+    while power != 0 {
+        power = signed_mul_div(power, z_squared, ONE_36)?;
+        n += 2;
+        sum += power / n;
+    }
+    Some((2 * sum + k as i128 * LN_2) / WAD)
+}
+
+/// `e` to the power of `x`, or None when it doesn't fit.
+pub fn exp(x: i128) -> Option<i128> {
+    // e^-42 is below the 18th decimal, and e^47 above the largest WAD
+    if x < -42 * WAD {
+        return Some(0);
+    }
+    if x > 47 * WAD {
+        return None;
+    }
+
+    // x = k ln 2 + r with r within ln 2 / 2 of zero, so e^x = 2^k e^r, with 36 decimals
+    let x = x * WAD;
+    let k = (x + x.signum() * (LN_2 / 2)) / LN_2;
+    let r = x - k * LN_2;
+
+    let (mut term, mut sum, mut n) = (ONE_36, ONE_36, 0);
+    while term != 0 {
+        n += 1;
+        term = signed_mul_div(term, r, ONE_36)? / n;
+        sum += term;
+    }
+    if k >= 0 {
+        signed_mul_div(sum, 1 << k, WAD)
+    } else {
+        signed_mul_div(sum, 1, WAD << -k)
+    }
+}
+
+/// `a * b / d` of signed numbers.
+fn signed_mul_div(a: i128, b: i128, d: i128) -> Option<i128> {
+    let magnitude = mul_div(a.unsigned_abs(), b.unsigned_abs(), d.unsigned_abs())?;
+    let magnitude = i128::try_from(magnitude).ok()?;
+    Some(if (a < 0) ^ (b < 0) ^ (d < 0) {
+        -magnitude
+    } else {
+        magnitude
+    })
+}
+
+/// `a * b / d`, without overflowing on the way when the result fits.
+fn mul_div(a: u128, b: u128, d: u128) -> Option<u128> {
+    if d == 0 {
+        return None;
+    }
+    if let Some(product) = a.checked_mul(b) {
+        return Some(product / d);
+    }
+
+    let product = U256::mul(a, b);
+    let quotient = if d <= LOW {
+        product.div_small(d)
+    } else if d.is_multiple_of(WAD as u128) && d / WAD as u128 <= LOW {
+        // such as one with 36 decimals, in two steps
+        product.div_small(WAD as u128).div_small(d / WAD as u128)
+    } else {
+        return product.div(d);
+    };
+    (quotient.high == 0).then_some(quotient.low)
+}
+
+/// The low 64 bits of a `u128`.
+const LOW: u128 = u64::MAX as u128;
+
+/// A 256 bits number, as its high and low halves.
+struct U256 {
+    high: u128,
+    low: u128,
+}
+
+impl U256 {
+    fn mul(a: u128, b: u128) -> Self {
+        let (a_high, a_low) = (a >> 64, a & LOW);
+        let (b_high, b_low) = (b >> 64, b & LOW);
+        let (low_low, high_low) = (a_low * b_low, a_high * b_low);
+        let (low_high, high_high) = (a_low * b_high, a_high * b_high);
+        let middle = (low_low >> 64) + (high_low & LOW) + (low_high & LOW);
+        Self {
+            high: high_high + (high_low >> 64) + (low_high >> 64) + (middle >> 64),
+            low: (low_low & LOW) | (middle << 64),
+        }
+    }
+
+    /// `self / d` for `d` that fits in 64 bits, 64 bits at a time.
+    fn div_small(self, d: u128) -> Self {
+        let mut remainder = 0;
+        let mut digits = [
+            self.high >> 64,
+            self.high & LOW,
+            self.low >> 64,
+            self.low & LOW,
+        ];
+        for digit in &mut digits {
+            let dividend = (remainder << 64) | *digit;
+            (*digit, remainder) = (dividend / d, dividend % d);
+        }
+        Self {
+            high: (digits[0] << 64) | digits[1],
+            low: (digits[2] << 64) | digits[3],
+        }
+    }
+
+    /// `self / d`, a bit at a time, or None when it doesn't fit in 128 bits.
+    fn div(self, d: u128) -> Option<u128> {
+        if self.high >= d {
+            return None;
+        }
+        let (mut remainder, mut quotient) = (self.high, 0);
+        for bit in (0..128).rev() {
+            let carry = remainder >> 127;
+            remainder = (remainder << 1) | ((self.low >> bit) & 1);
+            quotient <<= 1;
+            if carry == 1 || remainder >= d {
+                remainder = remainder.wrapping_sub(d);
+                quotient |= 1;
+            }
+        }
+        Some(quotient)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use dashu_float::DBig;
+
+    use super::*;
+
+    /// `x` exactly, with enough precision for the results to be exact to well past 18 decimals.
+    fn exact(x: i128) -> DBig {
+        DBig::from_str(&format!("{}e-18", x))
+            .unwrap()
+            .with_precision(40)
+            .value()
+    }
+
+    /// Whether `x` is within `tolerance` of `expected`.
+    fn near(x: i128, expected: &DBig, tolerance: DBig) -> bool {
+        let error = exact(x) - expected;
+        error <= tolerance && -error <= tolerance
+    }
+
+    #[test]
+    fn ln_and_exp_match_a_high_precision_reference() {
+        // from the smallest WAD to the largest, about 10 points per power of 10
+        let mut x: i128 = 1;
+        while let Some(next) = x.checked_add(x / 4 + 1) {
+            assert!(near(ln(x).unwrap(), &exact(x).ln(), exact(2)), "ln {}", x);
+            x = next;
+        }
+
+        // e^x within a unit of the 18th decimal, of the result when it's above 1
+        for quarters in -168..=186 {
+            let x = quarters * WAD / 4;
+            let expected = exact(x).exp();
+            let tolerance = (exact(WAD) + &expected) * exact(1);
+            assert!(near(exp(x).unwrap(), &expected, tolerance), "exp {}", x);
+        }
+
+        assert_eq!(ln(WAD), Some(0));
+        assert_eq!(exp(0), Some(WAD));
+        assert_eq!(ln(0), None);
+        assert_eq!(exp(47 * WAD), None);
+        assert_eq!(exp(-43 * WAD), Some(0));
+    }
+
+    #[test]
+    fn products_round_toward_zero_without_overflowing() {
+        assert_eq!(mul(3 * WAD / 2, -WAD / 2), Some(-3 * WAD / 4));
+        assert_eq!(mul(3, WAD / 2), Some(1));
+        assert_eq!(mul(-3, WAD / 2), Some(-1));
+        assert_eq!(div(WAD, 3 * WAD), Some(333_333_333_333_333_333));
+        assert_eq!(div(-WAD, 3 * WAD), Some(-333_333_333_333_333_333));
+        assert_eq!(div(WAD, 0), None);
+
+        // a product beyond 128 bits divided back down
+        let large = i128::MAX / 7;
+        assert_eq!(signed_mul_div(large, 7 * WAD, 7 * WAD), Some(large));
+        assert_eq!(mul(large, -WAD), Some(-large));
+        assert_eq!(mul(large, 8 * WAD), None);
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX), Some(u128::MAX));
+    }
+}
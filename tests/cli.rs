@@ -0,0 +1,91 @@
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn printing_scripts_leave_the_stream_clean() {
+    let script = std::env::temp_dir().join(format!("portimao-print-{}.lua", std::process::id()));
+    fs::write(
+        &script,
+        "print('HELLO') function takeYourTurn() print(GameState.turns) GameState:buy_acceleration(1) end",
+    )
+    .unwrap();
+
+    let car = |name: &str| format!("{}={}", name, script.display());
+    let output = Command::new(env!("CARGO_BIN_EXE_portimao"))
+        .args(["race", "--car", &car("alice"), "--car", &car("bob")])
+        .args(["--stream", "-", "--no-out"])
+        .output()
+        .unwrap();
+    fs::remove_file(&script).unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.lines().count() > 2);
+    for line in stdout.lines() {
+        serde_json::from_str::<serde_json::Value>(line).unwrap();
+    }
+    assert!(String::from_utf8(output.stderr).unwrap().contains("HELLO"));
+}
+
+#[test]
+fn sold_out_items_go_through_the_log() {
+    let dir = std::env::temp_dir().join(format!("portimao-sold-out-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("shell.lua");
+    fs::write(
+        &script,
+        "function takeYourTurn() GameState:buy_shell(1) GameState:buy_acceleration(1) end",
+    )
+    .unwrap();
+    // two shells can ever be sold
+    let config = dir.join("sold-out.toml");
+    fs::write(
+        &config,
+        "[shell]\nmodel = \"logistic\"\ntarget_price = 10\nper_turn_decrease = 0.2\n\
+         max_sellable = 2.0\ntime_scale = 0.5\n",
+    )
+    .unwrap();
+    let log = dir.join("log.json");
+
+    let car = |name: &str| format!("{}={}", name, script.display());
+    let race = Command::new(env!("CARGO_BIN_EXE_portimao"))
+        .args([
+            "race",
+            "--car",
+            &car("alice"),
+            "--car",
+            &car("bob"),
+            "--seed",
+            "1",
+        ])
+        .arg("--config")
+        .arg(&config)
+        .arg("--out")
+        .arg(&log)
+        .output()
+        .unwrap();
+    assert!(
+        race.status.success(),
+        "{}",
+        String::from_utf8_lossy(&race.stderr)
+    );
+
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&log).unwrap()).unwrap();
+    let last = json["turns"].as_array().unwrap().last().unwrap();
+    assert_eq!(last["costs"][2], serde_json::Value::Null);
+
+    let replay = Command::new(env!("CARGO_BIN_EXE_portimao"))
+        .arg("replay")
+        .arg(&log)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(
+        replay.status.success(),
+        "{}",
+        String::from_utf8_lossy(&replay.stderr)
+    );
+    assert!(String::from_utf8(replay.stdout)
+        .unwrap()
+        .contains("unaffordable"));
+}